@@ -0,0 +1,245 @@
+use crate::audio::{self, AudioState};
+use crate::error::AppError;
+use crate::queue::QueueItem;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Emitter, Manager, Runtime, State};
+use tokio::sync::Notify;
+
+const STORE_FILE: &str = "alarms.json";
+/// Longest the scheduler sleeps without looking at the clock, which may jump after a suspend.
+const RECHECK_INTERVAL: Duration = Duration::from_secs(30);
+/// Alarms noticed later than this, say after waking from sleep, are reported as missed
+/// instead of going off.
+const MISSED_AFTER: Duration = Duration::from_secs(5 * 60);
+const RAMP_TICK: Duration = Duration::from_millis(100);
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Alarm {
+    pub id: String,
+    /// Wall clock time in milliseconds since the Unix epoch.
+    pub time: u64,
+    pub items: Vec<QueueItem>,
+    pub start_index: usize,
+    /// Seconds the volume takes to rise from silence to `volume`.
+    pub fade: f32,
+    pub volume: f32,
+}
+
+/// Payload of `alarm_failed`, the alarm along with why its queue could not start.
+#[derive(Serialize, Clone)]
+struct AlarmFailure {
+    #[serde(flatten)]
+    alarm: Alarm,
+    error: String,
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+#[derive(Default)]
+struct AlarmStore {
+    alarms: Vec<Alarm>,
+    /// Alarms that did not go off, kept until the frontend picks them up.
+    missed: Vec<Alarm>,
+    file: Option<PathBuf>,
+}
+
+impl AlarmStore {
+    fn save(&self) {
+        let Some(file) = &self.file else {
+            return;
+        };
+        if let Some(dir) = file.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        if let Ok(data) = serde_json::to_vec(&self.alarms) {
+            let _ = fs::write(file, data);
+        }
+    }
+}
+
+/// Scheduled playback starts, persisted in the app data directory.
+#[derive(Default)]
+pub struct AlarmState {
+    store: Arc<Mutex<AlarmStore>>,
+    /// Wakes the scheduler when alarms are added or removed.
+    changed: Arc<Notify>,
+}
+
+impl AlarmState {
+    /// Loads saved alarms from `dir`, setting aside those that came due while the app was
+    /// closed, and starts the scheduler.
+    pub fn start<R: Runtime>(&self, app: AppHandle<R>, dir: Option<&Path>) {
+        {
+            let mut store = self.store.lock().unwrap();
+            if let Some(dir) = dir {
+                let file = dir.join(STORE_FILE);
+                if let Ok(data) = fs::read(&file) {
+                    let alarms: Vec<Alarm> = serde_json::from_slice(&data).unwrap_or_default();
+                    let now = now_millis();
+                    let (missed, alarms) = alarms.into_iter().partition(|a| a.time <= now);
+                    store.alarms = alarms;
+                    store.missed = missed;
+                }
+                store.file = Some(file);
+            }
+            if !store.missed.is_empty() {
+                store.save();
+                let _ = app.emit("alarms_missed", store.missed.clone());
+            }
+        }
+
+        let store = self.store.clone();
+        let changed = self.changed.clone();
+        tauri::async_runtime::spawn(async move {
+            loop {
+                let now = now_millis();
+                let (due, next) = {
+                    let mut store = store.lock().unwrap();
+                    let (due, pending): (Vec<Alarm>, Vec<Alarm>) =
+                        store.alarms.drain(..).partition(|a| a.time <= now);
+                    store.alarms = pending;
+                    let late: Vec<Alarm> = due
+                        .iter()
+                        .filter(|a| now - a.time > MISSED_AFTER.as_millis() as u64)
+                        .cloned()
+                        .collect();
+                    if !due.is_empty() {
+                        store.save();
+                    }
+                    if !late.is_empty() {
+                        store.missed.extend(late.iter().cloned());
+                        let _ = app.emit("alarms_missed", late);
+                    }
+                    let next = store.alarms.iter().map(|a| a.time).min();
+                    (due, next)
+                };
+
+                for alarm in due {
+                    if now - alarm.time <= MISSED_AFTER.as_millis() as u64 {
+                        tauri::async_runtime::spawn(fire(app.clone(), alarm));
+                    }
+                }
+
+                let wait = next
+                    .map_or(RECHECK_INTERVAL, |time| {
+                        Duration::from_millis(time.saturating_sub(now))
+                    })
+                    .min(RECHECK_INTERVAL);
+                tokio::select! {
+                    _ = tokio::time::sleep(wait) => {}
+                    _ = changed.notified() => {}
+                }
+            }
+        });
+    }
+}
+
+/// Starts the alarm's queue silent and brings the volume up over its fade.
+async fn fire<R: Runtime>(app: AppHandle<R>, alarm: Alarm) {
+    let state = app.state::<AudioState>();
+    if let Err(e) =
+        audio::start_queue_muted(&app, &state, alarm.items.clone(), alarm.start_index).await
+    {
+        let _ = app.emit("alarm_failed", AlarmFailure { alarm, error: e.to_string() });
+        return;
+    }
+    let _ = app.emit("alarm_fired", alarm.clone());
+
+    let fade = alarm.fade.max(0.0);
+    let steps = (fade / RAMP_TICK.as_secs_f32()).ceil().max(1.0) as u32;
+    let mut last = 0.0;
+    for step in 1..=steps {
+        if fade > 0.0 {
+            tokio::time::sleep(RAMP_TICK).await;
+        }
+        let sink = state.sink.lock().unwrap();
+        let Some(sink) = &*sink else {
+            return;
+        };
+        // Someone set the volume by hand, leave it where they put it
+        if (sink.volume() - last).abs() > 1e-3 {
+            return;
+        }
+        last = alarm.volume * step as f32 / steps as f32;
+        sink.set_volume(last);
+    }
+}
+
+/// Schedules `items` to start playing at `time`, milliseconds since the Unix epoch.
+#[tauri::command]
+pub fn schedule_alarm(
+    state: State<AlarmState>,
+    time: u64,
+    items: Vec<QueueItem>,
+    start_index: usize,
+    fade: f32,
+    volume: f32,
+) -> Result<Alarm, AppError> {
+    if time <= now_millis() {
+        return Err(AppError::InvalidOperation(
+            "Alarm time is in the past".to_string(),
+        ));
+    }
+    if start_index >= items.len() {
+        return Err(AppError::InvalidOperation(
+            "Queue index out of range".to_string(),
+        ));
+    }
+    if fade < 0.0 || !(0.0..=1.0).contains(&volume) {
+        return Err(AppError::InvalidOperation("Invalid alarm fade or volume".to_string()));
+    }
+
+    let created = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos());
+    let alarm = Alarm {
+        id: format!("{:x}", created),
+        time,
+        items,
+        start_index,
+        fade,
+        volume,
+    };
+    {
+        let mut store = state.store.lock().unwrap();
+        store.alarms.push(alarm.clone());
+        store.save();
+    }
+    state.changed.notify_one();
+    Ok(alarm)
+}
+
+#[tauri::command]
+pub fn cancel_alarm(state: State<AlarmState>, id: String) -> Result<(), AppError> {
+    let mut store = state.store.lock().unwrap();
+    let count = store.alarms.len();
+    store.alarms.retain(|a| a.id != id);
+    if store.alarms.len() == count {
+        return Err(AppError::InvalidOperation(format!("No alarm with id {}", id)));
+    }
+    store.save();
+    state.changed.notify_one();
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_alarms(state: State<AlarmState>) -> Result<Vec<Alarm>, AppError> {
+    let mut alarms = state.store.lock().unwrap().alarms.clone();
+    alarms.sort_by_key(|a| a.time);
+    Ok(alarms)
+}
+
+/// Returns the alarms missed since the last call, including those from before launch.
+#[tauri::command]
+pub fn take_missed_alarms(state: State<AlarmState>) -> Result<Vec<Alarm>, AppError> {
+    Ok(std::mem::take(&mut state.store.lock().unwrap().missed))
+}
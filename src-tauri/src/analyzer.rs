@@ -0,0 +1,307 @@
+use crate::error::AppError;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager, Runtime, State};
+
+const FFT_SIZE: usize = 2048;
+const MIN_RATE: u32 = 1;
+const MAX_RATE: u32 = 120;
+const MAX_BANDS: usize = 256;
+const LOWEST_FREQUENCY: f32 = 20.0;
+const HIGHEST_FREQUENCY: f32 = 20000.0;
+/// Floor band magnitudes are clamped to.
+const SILENCE_DB: f32 = -100.0;
+/// How often a hidden window is checked for becoming visible again.
+const HIDDEN_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AnalyzerSettings {
+    /// Frames emitted per second.
+    pub rate: u32,
+    /// Number of logarithmically spaced bands between 20 Hz and 20 kHz.
+    pub bands: usize,
+}
+
+impl Default for AnalyzerSettings {
+    fn default() -> Self {
+        AnalyzerSettings {
+            rate: 30,
+            bands: 64,
+        }
+    }
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ChannelLevel {
+    /// Linear sample peak since the last frame, above `1.0` when clipping.
+    pub peak: f32,
+    pub rms: f32,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MeterFrame {
+    /// Band magnitudes in dBFS, lowest band first.
+    pub bands: Vec<f32>,
+    pub levels: Vec<ChannelLevel>,
+    pub clipped: bool,
+}
+
+#[derive(Default)]
+struct TapBuffer {
+    channels: usize,
+    sample_rate: u32,
+    /// Most recent mono mix, at most `FFT_SIZE` samples.
+    recent: VecDeque<f32>,
+    peak: Vec<f32>,
+    squares: Vec<f64>,
+    frames: u64,
+}
+
+/// Copy of the mixed output taken right before it reaches the device. Does nothing while
+/// no one listens.
+#[derive(Default)]
+pub struct MeterTap {
+    enabled: AtomicBool,
+    buffer: Mutex<TapBuffer>,
+}
+
+impl MeterTap {
+    /// Takes in interleaved output samples.
+    pub fn feed(&self, samples: &[f32], channels: u16, sample_rate: u32) {
+        if !self.enabled.load(Ordering::Relaxed) {
+            return;
+        }
+        let channels = channels.max(1) as usize;
+        let mut buffer = self.buffer.lock().unwrap();
+        if buffer.channels != channels {
+            buffer.peak = vec![0.0; channels];
+            buffer.squares = vec![0.0; channels];
+            buffer.frames = 0;
+            buffer.channels = channels;
+        }
+        buffer.sample_rate = sample_rate;
+
+        for frame in samples.chunks_exact(channels) {
+            for (channel, &sample) in frame.iter().enumerate() {
+                buffer.peak[channel] = buffer.peak[channel].max(sample.abs());
+                buffer.squares[channel] += (sample * sample) as f64;
+            }
+            buffer.frames += 1;
+            buffer
+                .recent
+                .push_back(frame.iter().sum::<f32>() / channels as f32);
+        }
+        let excess = buffer.recent.len().saturating_sub(FFT_SIZE);
+        buffer.recent.drain(..excess);
+    }
+
+    fn set_enabled(&self, enabled: bool) {
+        if self.enabled.swap(enabled, Ordering::Relaxed) != enabled && !enabled {
+            *self.buffer.lock().unwrap() = TapBuffer::default();
+        }
+    }
+
+    /// Analyzes what came in since the last call.
+    fn frame(&self, bands: usize) -> MeterFrame {
+        let (samples, sample_rate, levels) = {
+            let mut buffer = self.buffer.lock().unwrap();
+            let frames = buffer.frames.max(1) as f64;
+            let levels: Vec<ChannelLevel> = buffer
+                .peak
+                .iter()
+                .zip(&buffer.squares)
+                .map(|(&peak, &squares)| ChannelLevel {
+                    peak,
+                    rms: (squares / frames).sqrt() as f32,
+                })
+                .collect();
+            buffer.peak.iter_mut().for_each(|p| *p = 0.0);
+            buffer.squares.iter_mut().for_each(|s| *s = 0.0);
+            buffer.frames = 0;
+            let samples: Vec<f32> = buffer.recent.iter().copied().collect();
+            (samples, buffer.sample_rate, levels)
+        };
+
+        MeterFrame {
+            bands: spectrum(&samples, sample_rate, bands),
+            clipped: levels.iter().any(|level| level.peak >= 1.0),
+            levels,
+        }
+    }
+}
+
+/// Band magnitudes in dBFS of the latest `FFT_SIZE` samples.
+fn spectrum(samples: &[f32], sample_rate: u32, bands: usize) -> Vec<f32> {
+    if samples.len() < FFT_SIZE || sample_rate == 0 {
+        return vec![SILENCE_DB; bands];
+    }
+
+    let mut re: Vec<f32> = samples
+        .iter()
+        .enumerate()
+        .map(|(i, &x)| x * (0.5 - 0.5 * (2.0 * PI * i as f32 / FFT_SIZE as f32).cos()))
+        .collect();
+    let mut im = vec![0.0; FFT_SIZE];
+    fft(&mut re, &mut im);
+
+    // Hann window gain is one half, a full scale sine ends up at 0 dB
+    let scale = 4.0 / FFT_SIZE as f32;
+    let magnitudes: Vec<f32> = (0..FFT_SIZE / 2)
+        .map(|i| (re[i] * re[i] + im[i] * im[i]).sqrt() * scale)
+        .collect();
+
+    let bin_width = sample_rate as f32 / FFT_SIZE as f32;
+    let highest = HIGHEST_FREQUENCY.min(sample_rate as f32 / 2.0);
+    let ratio = (highest / LOWEST_FREQUENCY).powf(1.0 / bands as f32);
+    (0..bands)
+        .map(|band| {
+            let low = LOWEST_FREQUENCY * ratio.powi(band as i32);
+            let first = ((low / bin_width) as usize).min(magnitudes.len() - 1);
+            let last = (((low * ratio) / bin_width) as usize).clamp(first + 1, magnitudes.len());
+            let magnitude = magnitudes[first..last].iter().cloned().fold(0.0, f32::max);
+            (20.0 * magnitude.max(1e-10).log10()).max(SILENCE_DB)
+        })
+        .collect()
+}
+
+/// In-place iterative radix-2 FFT, `re.len()` being a power of two.
+fn fft(re: &mut [f32], im: &mut [f32]) {
+    let n = re.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * PI / len as f32;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = (angle * k as f32).sin_cos();
+                let (a, b) = (start + k, start + k + len / 2);
+                let tr = re[b] * cos - im[b] * sin;
+                let ti = re[b] * sin + im[b] * cos;
+                re[b] = re[a] - tr;
+                im[b] = im[a] - ti;
+                re[a] += tr;
+                im[a] += ti;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+/// Runs the spectrum and level meter while the frontend is subscribed to it.
+pub struct AnalyzerState {
+    tap: Arc<MeterTap>,
+    settings: Arc<Mutex<AnalyzerSettings>>,
+    subscribers: Arc<Mutex<usize>>,
+    /// Bumped for every emitter task started, older ones exit when they see it changed.
+    generation: Arc<AtomicU64>,
+}
+
+impl AnalyzerState {
+    pub fn new(tap: Arc<MeterTap>) -> Self {
+        AnalyzerState {
+            tap,
+            settings: Arc::new(Mutex::new(AnalyzerSettings::default())),
+            subscribers: Arc::new(Mutex::new(0)),
+            generation: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    fn spawn<R: Runtime>(&self, app: AppHandle<R>) {
+        let tap = self.tap.clone();
+        let settings = self.settings.clone();
+        let subscribers = self.subscribers.clone();
+        let generation = self.generation.clone();
+        let task = generation.fetch_add(1, Ordering::AcqRel) + 1;
+        tauri::async_runtime::spawn(async move {
+            loop {
+                let settings = *settings.lock().unwrap();
+                if generation.load(Ordering::Acquire) != task {
+                    break;
+                }
+                if *subscribers.lock().unwrap() == 0 {
+                    tap.set_enabled(false);
+                    break;
+                }
+
+                let visible = app.get_webview_window("main").is_some_and(|window| {
+                    window.is_visible().unwrap_or(false) && !window.is_minimized().unwrap_or(false)
+                });
+                tap.set_enabled(visible);
+                if !visible {
+                    tokio::time::sleep(HIDDEN_POLL_INTERVAL).await;
+                    continue;
+                }
+
+                tokio::time::sleep(Duration::from_secs(1) / settings.rate).await;
+                let _ = app.emit("meter_event", tap.frame(settings.bands));
+            }
+        });
+    }
+}
+
+/// Starts `meter_event` emission, or joins it when another view already subscribed.
+#[tauri::command]
+pub fn subscribe_meter<R: Runtime>(
+    app: AppHandle<R>,
+    state: State<AnalyzerState>,
+) -> Result<(), AppError> {
+    let mut subscribers = state.subscribers.lock().unwrap();
+    *subscribers += 1;
+    if *subscribers == 1 {
+        state.spawn(app);
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn unsubscribe_meter(state: State<AnalyzerState>) -> Result<(), AppError> {
+    let mut subscribers = state.subscribers.lock().unwrap();
+    *subscribers = subscribers.saturating_sub(1);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn set_meter_settings(
+    state: State<AnalyzerState>,
+    settings: AnalyzerSettings,
+) -> Result<(), AppError> {
+    if !(MIN_RATE..=MAX_RATE).contains(&settings.rate) {
+        return Err(AppError::InvalidOperation(format!(
+            "Meter rate must be between {} and {} Hz",
+            MIN_RATE, MAX_RATE
+        )));
+    }
+    if settings.bands == 0 || settings.bands > MAX_BANDS {
+        return Err(AppError::InvalidOperation(format!(
+            "Meter supports 1 to {} bands",
+            MAX_BANDS
+        )));
+    }
+    *state.settings.lock().unwrap() = settings;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_meter_settings(state: State<AnalyzerState>) -> Result<AnalyzerSettings, AppError> {
+    Ok(*state.settings.lock().unwrap())
+}
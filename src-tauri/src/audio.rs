@@ -1,206 +1,702 @@
+use crate::cache::{CacheWriter, StreamCache};
+use crate::channels::{ChannelControl, ChannelMixer, ChannelSettings};
+use crate::equalizer::{Equalizer, EqualizerControl};
 use crate::error::AppError;
+use crate::hls;
+use crate::http::{HttpClient, HttpSettings, RequestOptions, Requester};
+use crate::fade::{
+    FadeControl, FadeCurve, Fader, TransportFade, TransportFadeSettings, TransportFader,
+};
+use crate::icy::{self, IcyDemuxer};
+use crate::local_scanner::{self, ReplayGain};
+use crate::loudness::{self, LoudnessStore};
+use crate::media_control::{self, MediaControlState};
+use crate::queue::{PlayQueue, QueueItem, RepeatMode, ShuffleMode, TrackSource};
+use crate::stream::{Overflow, StreamBuffer, StreamDecoder};
+use crate::stretch::{
+    MediaClock, SpeedMode, TempoControl, TimeStretch, MAX_PITCH_CENTS, MAX_SPEED, MIN_SPEED,
+};
+use crate::waveform::WaveformStore;
 use lofty::file::AudioFile;
 use lofty::probe::Probe;
-use rodio::{Decoder, Sink, Source, mixer::Mixer};
+use rodio::{Decoder, Sample, Sink, Source, mixer::Mixer};
+use std::collections::VecDeque;
 use std::fs::File;
-use std::io::{BufReader, Cursor, Read, Seek, SeekFrom};
+use std::io::{BufReader, Cursor};
 use std::path::Path;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, Condvar, Mutex};
-use std::time::Duration;
-use tauri::{Runtime, State, Emitter};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::{Runtime, State, Emitter, Manager};
 use reqwest;
 use futures_util::StreamExt;
 use tokio::sync::mpsc;
 
+/// How long before the current track ends the next queue entry gets decoded and appended.
+const PRELOAD_WINDOW: Duration = Duration::from_secs(15);
+const MAX_CROSSFADE: Duration = Duration::from_secs(12);
+/// Longest ramp allowed around pause, resume, stop and seek.
+const MAX_TRANSPORT_FADE: Duration = Duration::from_secs(1);
+/// Bytes fetched from the start of a stream to read its duration from.
+const PROBE_BYTES: usize = 64 * 1024;
+/// Offsets this far ahead of the running download are fetched with a new `Range` request
+/// instead of waiting for the download to get there.
+const RANGE_JUMP_BYTES: u64 = 512 * 1024;
+/// Downloaded bytes between attempts to apply a seek whose target was not buffered yet.
+const SEEK_RETRY_BYTES: usize = 256 * 1024;
+/// Smallest memory limit for stream buffers, in MiB.
+const MIN_BUFFER_MEMORY: u32 = 4;
+/// How long the playing track's position may stand still before it counts as stalled.
+const STALL_THRESHOLD: Duration = Duration::from_millis(750);
+/// Loudness that ReplayGain gains are relative to, used to turn measurements into gains.
+const REPLAYGAIN_REFERENCE_LUFS: f64 = -18.0;
+/// Largest normalization pre-amp either way, in dB.
+const MAX_PRE_AMP: f32 = 15.0;
+
+type DecodedSource = Box<dyn Source<Item = i16> + Send>;
+type BoxedSource = Box<dyn Source<Item = f32> + Send>;
+
+#[derive(Clone, Copy, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum EndReason {
+    /// Played out, or faded out by a crossfade into the next track.
+    Finished,
+    /// Left behind by a skip or by another track being started.
+    Skipped,
+    /// Cut short because its stream could not be downloaded completely.
+    Error,
+}
+
+/// `generation` in the lifecycle events identifies one playback of a track, so events
+/// about a track that has since been replaced can be told apart and ignored.
 #[derive(Clone, serde::Serialize)]
 #[serde(tag = "type", content = "data")]
 pub enum PlaybackEvent {
     BufferUpdate { buffer_progress: f32 },
     BufferSeekReady,
     UpdateProgress { progress: f32 },
+    TrackChanged { index: usize, id: String },
+    TrackStarted { generation: u64, id: String },
+    TrackEnded { generation: u64, id: String, reason: EndReason },
+    PlaybackError { generation: u64, id: String, error: String },
+    /// The playing track stopped advancing although it is not paused, usually while its
+    /// stream waits for the download.
+    Stalled { generation: u64 },
+    Resumed { generation: u64 },
+    /// A radio station announced what it is playing now.
+    StreamTitle { generation: u64, title: String },
 }
 
 pub struct AudioState {
     pub sink: Arc<Mutex<Option<Sink>>>,
     pub stream: Arc<Mixer<f32>>,
-    pub buffer: Arc<Mutex<Vec<u8>>>,
-    pub is_stream_ended: Arc<AtomicBool>,
-    pub decoder: Arc<Mutex<Option<Decoder<Cursor<Vec<u8>>>>>>,
-    pub data_available: Arc<(Mutex<bool>, Condvar)>,
     pub seek_target: Arc<Mutex<Option<Duration>>>,
-    pub buffered_duration: Arc<Mutex<Duration>>,
     pub progress_tx: Arc<Mutex<Option<mpsc::Sender<PlaybackEvent>>>>,
+    pub queue: Arc<Mutex<PlayQueue>>,
+    pub tracks: Arc<Mutex<LoadedTracks>>,
+    pub crossfade: Arc<Mutex<CrossfadeSettings>>,
+    /// Sinks of tracks that are fading out underneath the current one.
+    pub outgoing: Arc<Mutex<Vec<Sink>>>,
+    pub normalization: Arc<Mutex<NormalizationSettings>>,
+    pub loudness: Arc<Mutex<LoudnessStore>>,
+    pub equalizer: Arc<EqualizerControl>,
+    pub tempo: Arc<TempoControl>,
+    pub channels: Arc<ChannelControl>,
+    pub transport: Arc<TransportFade>,
+    pub cache: Arc<Mutex<StreamCache>>,
+    pub buffering: Arc<Mutex<BufferSettings>>,
+    pub http: Arc<HttpClient>,
+    pub waveforms: Arc<Mutex<WaveformStore>>,
 }
 
-struct StreamingSource {
-    buffer: Arc<Mutex<Vec<u8>>>,
-    is_stream_ended: Arc<AtomicBool>,
-    decoder: Arc<Mutex<Option<Decoder<Cursor<Vec<u8>>>>>>,
-    position: usize,
-    data_available: Arc<(Mutex<bool>, Condvar)>,
-    sample_rate: u32,
-    channels: u16,
+#[derive(Clone, Copy, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CrossfadeSettings {
+    /// Overlap between consecutive tracks in seconds, `0` keeps playback gapless.
+    pub duration: f32,
+    pub curve: FadeCurve,
 }
 
-impl StreamingSource {
-    fn new(
-        buffer: Arc<Mutex<Vec<u8>>>,
-        is_stream_ended: Arc<AtomicBool>,
-        decoder: Arc<Mutex<Option<Decoder<Cursor<Vec<u8>>>>>>,
-        data_available: Arc<(Mutex<bool>, Condvar)>,
-    ) -> Self {
-        StreamingSource {
-            buffer,
-            is_stream_ended,
-            decoder,
-            position: 0,
-            data_available,
-            sample_rate: 44100,
-            channels: 2,
+impl CrossfadeSettings {
+    fn overlap(&self) -> Option<Duration> {
+        (self.duration > 0.0).then(|| Duration::from_secs_f32(self.duration))
+    }
+}
+
+#[derive(Clone, Copy, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BufferSettings {
+    /// MiB of downloaded audio a stream keeps in memory. Beyond it streams spill to a
+    /// temporary file, and radio drops what was already played.
+    pub memory_limit: u32,
+}
+
+impl Default for BufferSettings {
+    fn default() -> Self {
+        BufferSettings { memory_limit: 64 }
+    }
+}
+
+impl BufferSettings {
+    fn memory_limit(&self) -> u64 {
+        self.memory_limit as u64 * 1024 * 1024
+    }
+}
+
+#[derive(Clone, Copy, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum NormalizationMode {
+    #[default]
+    Off,
+    Track,
+    Album,
+}
+
+#[derive(Clone, Copy, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NormalizationSettings {
+    pub mode: NormalizationMode,
+    /// Extra gain in dB applied on top of the track or album gain.
+    pub pre_amp: f32,
+    /// Lowers the gain so the tagged or measured peak never exceeds full scale.
+    pub prevent_clipping: bool,
+    /// Measures and stores the loudness of files without gain tags.
+    pub measure_untagged: bool,
+}
+
+impl Default for NormalizationSettings {
+    fn default() -> Self {
+        NormalizationSettings {
+            mode: NormalizationMode::Off,
+            pre_amp: 0.0,
+            prevent_clipping: true,
+            measure_untagged: true,
         }
     }
+}
 
-    fn reset(&mut self) {
-        self.position = 0;
-        *self.decoder.lock().unwrap() = None;
+/// Applies a fixed loudness normalization gain to a track.
+struct Normalizer<S> {
+    inner: S,
+    gain: f32,
+}
+
+impl<S> Iterator for Normalizer<S>
+where
+    S: Source,
+    S::Item: Sample,
+{
+    type Item = S::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|sample| sample.amplify(self.gain))
+    }
+}
+
+impl<S> Source for Normalizer<S>
+where
+    S: Source,
+    S::Item: Sample,
+{
+    fn current_span_len(&self) -> Option<usize> {
+        self.inner.current_span_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
     }
 
     fn try_seek(&mut self, pos: Duration) -> std::result::Result<(), rodio::source::SeekError> {
-        let buffer = self.buffer.lock().unwrap();
-        
-        // Create a temporary decoder to get accurate audio parameters
-        if let Ok(temp_decoder) = Decoder::new(Cursor::new(buffer.clone())) {
-            self.sample_rate = temp_decoder.sample_rate();
-            self.channels = temp_decoder.channels();
-            
-            // Calculate bytes per second (2 bytes per sample)
-            let bytes_per_second = self.sample_rate as f64 * self.channels as f64 * 2.0;
-            let target_position = (pos.as_secs_f64() * bytes_per_second / 7.34) as usize;
-            
-            if target_position >= buffer.len() {
-                return Err(rodio::source::SeekError::NotSupported {
-                    underlying_source: "position beyond buffer",
-                });
+        self.inner.try_seek(pos)
+    }
+}
+
+/// Shifts the pitch by reading its input faster or slower with cubic interpolation. The time
+/// stretch in front of it has already made up for the tempo change that comes with that.
+struct PitchShift<S> {
+    inner: S,
+    control: Arc<TempoControl>,
+    channels: usize,
+    /// Four consecutive input frames, interleaved. Output is interpolated between the second
+    /// and third.
+    window: Vec<f32>,
+    fraction: f32,
+    /// Samples left to hand out before going back to plain playback, or the current frame.
+    output: Vec<f32>,
+    cursor: usize,
+    active: bool,
+}
+
+impl<S> PitchShift<S>
+where
+    S: Source<Item = f32>,
+{
+    fn new(inner: S, control: Arc<TempoControl>) -> Self {
+        PitchShift {
+            channels: inner.channels().max(1) as usize,
+            inner,
+            control,
+            window: Vec::new(),
+            fraction: 0.0,
+            output: Vec::new(),
+            cursor: 0,
+            active: false,
+        }
+    }
+
+    /// Slides the window one frame on, false once the inner source ran out.
+    fn push_frame(&mut self) -> bool {
+        let ch = self.channels;
+        if self.window.len() >= 4 * ch {
+            self.window.drain(..ch);
+        }
+        for _ in 0..ch {
+            match self.inner.next() {
+                Some(sample) => self.window.push(sample),
+                None => return false,
             }
+        }
+        true
+    }
+
+    fn start(&mut self) -> bool {
+        self.channels = self.inner.channels().max(1) as usize;
+        self.window.clear();
+        self.fraction = 0.0;
+        // Repeat the first frame in front so interpolation can start right away
+        if !self.push_frame() {
+            return false;
+        }
+        self.window.extend_from_within(..self.channels);
+        self.active = self.push_frame() && self.push_frame();
+        if !self.active {
+            self.stop();
+        }
+        true
+    }
 
-            // Ensure position aligns with frame boundaries
-            let frame_size = self.channels as usize * 2;
-            self.position = target_position - (target_position % frame_size);
-            
-            // Create new decoder starting from target position
-            if let Ok(new_decoder) = Decoder::new(Cursor::new(buffer[self.position..].to_vec())) {
-                let mut decoder = self.decoder.lock().unwrap();
-                *decoder = Some(new_decoder);
-                return Ok(());
+    /// Hands out the frames still ahead in the window unchanged.
+    fn stop(&mut self) {
+        self.active = false;
+        let ahead = (2 * self.channels).min(self.window.len());
+        self.output.extend_from_slice(&self.window[self.window.len() - ahead..]);
+        self.window.clear();
+    }
+
+    fn interpolate(&mut self, ratio: f32) {
+        let (ch, t) = (self.channels, self.fraction);
+        for c in 0..ch {
+            let [y0, y1, y2, y3] = [0, 1, 2, 3].map(|i| self.window[i * ch + c]);
+            // Catmull-Rom spline through the four frames
+            let a = -0.5 * y0 + 1.5 * y1 - 1.5 * y2 + 0.5 * y3;
+            let b = y0 - 2.5 * y1 + 2.0 * y2 - 0.5 * y3;
+            let c1 = -0.5 * y0 + 0.5 * y2;
+            self.output.push(((a * t + b) * t + c1) * t + y1);
+        }
+
+        self.fraction += ratio;
+        while self.fraction >= 1.0 {
+            self.fraction -= 1.0;
+            if !self.push_frame() {
+                self.stop();
+                return;
             }
         }
-        
-        Err(rodio::source::SeekError::NotSupported {
-            underlying_source: "decoder creation failed",
-        })
     }
 }
 
-impl Read for StreamingSource {
-    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-        let mut total_read = 0;
-        while total_read < buf.len() {
-            let buffer = self.buffer.lock().unwrap();
-            if self.position < buffer.len() {
-                let remaining = buffer.len() - self.position;
-                let to_read = std::cmp::min(remaining, buf.len() - total_read);
-                buf[total_read..total_read + to_read]
-                    .copy_from_slice(&buffer[self.position..self.position + to_read]);
-                self.position += to_read;
-                total_read += to_read;
-            } else if self.is_stream_ended.load(Ordering::Relaxed) {
-                break;
-            } else {
-                drop(buffer);
-                let (lock, cvar) = &*self.data_available;
-                let mut available = lock.lock().unwrap();
-                if !*available {
-                    available = cvar.wait(available).unwrap();
+impl<S> Iterator for PitchShift<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.cursor == self.output.len() {
+            self.output.clear();
+            self.cursor = 0;
+
+            let ratio = self.control.pitch();
+            let format_changed = self.inner.channels().max(1) as usize != self.channels;
+            if self.active && (ratio == 1.0 || format_changed) {
+                self.stop();
+            } else if self.active {
+                self.interpolate(ratio);
+            } else if ratio != 1.0 && self.start() {
+                if self.active {
+                    self.interpolate(ratio);
                 }
-                *available = false;
+            } else {
+                return self.inner.next();
+            }
+            if self.output.is_empty() {
+                return None;
             }
         }
-        Ok(total_read)
+
+        let sample = self.output[self.cursor];
+        self.cursor += 1;
+        Some(sample)
     }
 }
 
-impl Seek for StreamingSource {
-    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
-        let buffer_len = self.buffer.lock().unwrap().len() as u64;
-        let new_pos = match pos {
-            SeekFrom::Start(offset) => offset,
-            SeekFrom::Current(offset) => (self.position as i64 + offset).try_into().unwrap(),
-            SeekFrom::End(offset) => (buffer_len as i64 + offset).try_into().unwrap(),
-        };
+impl<S> Source for PitchShift<S>
+where
+    S: Source<Item = f32>,
+{
+    fn current_span_len(&self) -> Option<usize> {
+        match self.active || self.cursor < self.output.len() {
+            true => None,
+            false => self.inner.current_span_len(),
+        }
+    }
 
-        if new_pos > (buffer_len as i64).try_into().unwrap() {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidInput,
-                "Invalid seek position",
-            ));
+    fn channels(&self) -> u16 {
+        match self.active || self.cursor < self.output.len() {
+            true => self.channels as u16,
+            false => self.inner.channels(),
         }
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
 
-        self.position = new_pos as usize;
-        self.reset();
-        Ok(self.position as u64)
+    fn try_seek(&mut self, pos: Duration) -> std::result::Result<(), rodio::source::SeekError> {
+        self.inner.try_seek(pos)?;
+        self.window.clear();
+        self.output.clear();
+        self.cursor = 0;
+        self.active = false;
+        Ok(())
     }
 }
 
-impl Iterator for StreamingSource {
-    type Item = i16;
+/// Picks the gain in dB and matching peak for `mode`, falling back to the other kind of gain.
+fn select_replay_gain(gain: &ReplayGain, mode: NormalizationMode) -> Option<(f32, Option<f32>)> {
+    let track = gain.track_gain.map(|g| (g, gain.track_peak));
+    let album = gain.album_gain.map(|g| (g, gain.album_peak));
+    match mode {
+        NormalizationMode::Off => None,
+        NormalizationMode::Track => track.or(album),
+        NormalizationMode::Album => album.or(track),
+    }
+}
+
+/// Linear gain normalizing a local file, from its tags or a stored loudness measurement.
+fn normalization_gain(state: &AudioState, path: &Path) -> Option<f32> {
+    let settings = *state.normalization.lock().unwrap();
+    if settings.mode == NormalizationMode::Off {
+        return None;
+    }
+
+    let tagged = local_scanner::probe_replay_gain(path)
+        .and_then(|gain| select_replay_gain(&gain, settings.mode));
+    let (gain_db, peak) = match tagged {
+        Some(tagged) => tagged,
+        None => {
+            let measured = loudness::lookup(&state.loudness, path, settings.measure_untagged)?;
+            (
+                (REPLAYGAIN_REFERENCE_LUFS - measured.loudness) as f32,
+                Some(measured.peak),
+            )
+        }
+    };
+
+    let mut gain = 10f32.powf((gain_db + settings.pre_amp) / 20.0);
+    if settings.prevent_clipping {
+        if let Some(peak) = peak.filter(|p| *p > 0.0) {
+            gain = gain.min(1.0 / peak);
+        }
+    }
+    Some(gain)
+}
+
+#[derive(Default)]
+struct TrackControl {
+    cancelled: AtomicBool,
+    finished: AtomicBool,
+}
+
+impl TrackControl {
+    fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    fn is_finished(&self) -> bool {
+        self.finished.load(Ordering::Relaxed)
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// Wraps a track appended to the shared `Sink` so it can report when it has
+/// been played out and be dropped without clearing the rest of the sink.
+struct QueuedSource<S> {
+    inner: S,
+    control: Arc<TrackControl>,
+}
+
+impl<S> Iterator for QueuedSource<S>
+where
+    S: Source,
+    S::Item: Sample,
+{
+    type Item = S::Item;
 
     fn next(&mut self) -> Option<Self::Item> {
-        loop {
-            let mut decoder = self.decoder.lock().unwrap();
-            if let Some(ref mut dec) = *decoder {
-                if let Some(sample) = dec.next() {
-                    return Some(sample);
-                }
+        if self.control.cancelled.load(Ordering::Relaxed) {
+            self.control.finished.store(true, Ordering::Relaxed);
+            return None;
+        }
+
+        let sample = self.inner.next();
+        if sample.is_none() {
+            self.control.finished.store(true, Ordering::Relaxed);
+        }
+        sample
+    }
+}
+
+impl<S> Source for QueuedSource<S>
+where
+    S: Source,
+    S::Item: Sample,
+{
+    fn current_span_len(&self) -> Option<usize> {
+        self.inner.current_span_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> std::result::Result<(), rodio::source::SeekError> {
+        self.inner.try_seek(pos)
+    }
+}
+
+struct OpenedTrack {
+    source: BoxedSource,
+    clock: Arc<MediaClock>,
+    duration: Option<Duration>,
+    stream: Option<Arc<StreamBuffer>>,
+}
+
+#[derive(Clone)]
+struct LoadedTrack {
+    index: usize,
+    id: String,
+    /// Playback generation carried by the lifecycle events about this track.
+    playback: u64,
+    duration: Option<Duration>,
+    stream: Option<Arc<StreamBuffer>>,
+    clock: Arc<MediaClock>,
+    control: Arc<TrackControl>,
+    fade: Arc<FadeControl>,
+}
+
+/// A track that has been opened but is held back until the crossfade into it starts.
+struct PendingTrack {
+    index: usize,
+    item: QueueItem,
+    opened: OpenedTrack,
+}
+
+impl LoadedTrack {
+    fn duration(&self) -> Option<Duration> {
+        self.duration
+            .or_else(|| self.stream.as_ref().and_then(|s| *s.duration.lock().unwrap()))
+    }
+
+    fn position(&self) -> Duration {
+        self.clock.position()
+    }
+}
+
+/// Tracks currently appended to the sink, front being the one that is playing.
+#[derive(Default)]
+pub struct LoadedTracks {
+    tracks: VecDeque<LoadedTrack>,
+    /// Queue index of the last track handed to the sink, or skipped because it failed to load.
+    tail: Option<usize>,
+    /// Bumped whenever loaded tracks are discarded so in-flight preloads can tell they are stale.
+    generation: u64,
+    preloading: bool,
+    pending: Option<PendingTrack>,
+    /// Last playback generation handed out, never reset.
+    playbacks: u64,
+    /// Bumped by every start, so a start overtaken while opening its track can give up.
+    starts: u64,
+}
+
+impl LoadedTracks {
+    fn reset(&mut self) {
+        for track in &self.tracks {
+            track.control.cancel();
+        }
+        self.tracks.clear();
+        self.tail = None;
+        self.generation += 1;
+        self.preloading = false;
+        self.pending = None;
+    }
+
+    /// Drops every track queued behind the current one so the next entry is reloaded from the queue.
+    fn drop_preloaded(&mut self) {
+        while self.tracks.len() > 1 {
+            if let Some(track) = self.tracks.pop_back() {
+                track.control.cancel();
             }
+        }
+        self.tail = self.tracks.front().map(|t| t.index);
+        self.generation += 1;
+        self.preloading = false;
+        self.pending = None;
+    }
 
-            drop(decoder);
+    fn next_start(&mut self) -> u64 {
+        self.starts += 1;
+        self.starts
+    }
 
-            let buffer = self.buffer.lock().unwrap();
-            if self.position < buffer.len() {
-                let new_buffer = buffer[self.position..].to_vec();
-                self.position = buffer.len();
-                drop(buffer);
+    fn next_playback(&mut self) -> u64 {
+        self.playbacks += 1;
+        self.playbacks
+    }
 
-                let mut decoder = self.decoder.lock().unwrap();
-                if let Ok(new_decoder) = Decoder::new(Cursor::new(new_buffer)) {
-                    self.sample_rate = new_decoder.sample_rate();
-                    self.channels = new_decoder.channels();
-                    *decoder = Some(new_decoder);
-                }
-            } else if self.is_stream_ended.load(Ordering::Relaxed) {
-                return None;
-            } else {
-                // Wait for more data
-                drop(buffer);
-                let (lock, cvar) = &*self.data_available;
-                let mut available = lock.lock().unwrap();
-                if !*available {
-                    available = cvar.wait(available).unwrap();
-                }
-                *available = false;
+    fn push(
+        &mut self,
+        sink: &Sink,
+        index: usize,
+        item: &QueueItem,
+        opened: OpenedTrack,
+        fade: FadeControl,
+    ) {
+        let playback = self.next_playback();
+        let control = Arc::new(TrackControl::default());
+        let fade = Arc::new(fade);
+        sink.append(QueuedSource {
+            inner: Fader::new(opened.source, fade.clone()),
+            control: control.clone(),
+        });
+        self.tracks.push_back(LoadedTrack {
+            index,
+            id: item.id.clone(),
+            playback,
+            duration: opened.duration,
+            stream: opened.stream,
+            clock: opened.clock,
+            control,
+            fade,
+        });
+        self.tail = Some(index);
+    }
+
+    /// Appends the track held back for a crossfade straight onto the sink.
+    fn promote_pending(&mut self, sink: &Sink) -> bool {
+        match self.pending.take() {
+            Some(pending) => {
+                self.push(sink, pending.index, &pending.item, pending.opened, FadeControl::default());
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn current(&self) -> Option<&LoadedTrack> {
+        self.tracks.front()
+    }
+
+    fn is_current_stream(&self, stream: &Arc<StreamBuffer>) -> bool {
+        self.current()
+            .and_then(|t| t.stream.as_ref())
+            .is_some_and(|s| Arc::ptr_eq(s, stream))
+    }
+}
+
+/// Plays a `StreamBuffer` through one symphonia reader and decoder for the whole track. Its reads
+/// wait for the download instead of ending, so codec state survives a slow connection.
+struct StreamingSource {
+    stream: Arc<StreamBuffer>,
+    decoder: Option<StreamDecoder>,
+    sample_rate: u32,
+    channels: u16,
+}
+
+impl StreamingSource {
+    fn new(stream: Arc<StreamBuffer>) -> Self {
+        StreamingSource {
+            stream,
+            decoder: None,
+            sample_rate: 44100,
+            channels: 2,
+        }
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> std::result::Result<(), rodio::source::SeekError> {
+        // Keep playing from the current decoder until the target is actually reachable
+        let decoder = StreamDecoder::open_at(self.stream.clone(), pos).map_err(|_| {
+            rodio::source::SeekError::NotSupported {
+                underlying_source: "position not buffered yet",
             }
+        })?;
+
+        self.sample_rate = decoder.sample_rate();
+        self.channels = decoder.channels();
+        self.decoder = Some(decoder);
+        Ok(())
+    }
+}
+
+impl Drop for StreamingSource {
+    /// Nothing else plays the stream, so its download can stop.
+    fn drop(&mut self) {
+        self.stream.cancel();
+    }
+}
+
+impl Iterator for StreamingSource {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.decoder.is_none() {
+            self.decoder = Some(StreamDecoder::open(self.stream.clone(), None).ok()?);
         }
+
+        let decoder = self.decoder.as_mut()?;
+        let sample = decoder.next();
+        self.sample_rate = decoder.sample_rate();
+        self.channels = decoder.channels();
+        sample
     }
 }
 
 impl Source for StreamingSource {
     fn current_span_len(&self) -> Option<usize> {
-        self.decoder
-            .lock()
-            .unwrap()
-            .as_ref()
-            .and_then(|d| d.current_span_len())
+        self.decoder.as_ref().and_then(|d| d.current_span_len())
     }
 
     fn channels(&self) -> u16 {
@@ -212,11 +708,7 @@ impl Source for StreamingSource {
     }
 
     fn total_duration(&self) -> Option<Duration> {
-        let buffer = self.buffer.lock().unwrap();
-        let total_samples = buffer.len() / 2 / self.channels() as usize;
-        Some(Duration::from_secs_f64(
-            total_samples as f64 / self.sample_rate() as f64,
-        ))
+        *self.stream.duration.lock().unwrap()
     }
 
     fn try_seek(&mut self, pos: Duration) -> std::result::Result<(), rodio::source::SeekError> {
@@ -224,210 +716,1036 @@ impl Source for StreamingSource {
     }
 }
 
-fn spawn_progress_task<R: Runtime>(
-    sink_ref: Arc<Mutex<Option<Sink>>>,
+/// Starts the `playback_event` forwarder and the playback monitor once, returning the event sender.
+fn ensure_event_loop<R: Runtime>(
+    app: &tauri::AppHandle<R>,
+    state: &AudioState,
+) -> mpsc::Sender<PlaybackEvent> {
+    let mut tx_guard = state.progress_tx.lock().unwrap();
+    if let Some(tx) = tx_guard.as_ref() {
+        return tx.clone();
+    }
+
+    let (tx, mut rx) = mpsc::channel(32);
+    *tx_guard = Some(tx.clone());
+
+    // Spawn event handler
+    let app_handle = app.clone();
+    tokio::spawn(async move {
+        while let Some(event) = rx.recv().await {
+            let _ = app_handle.emit("playback_event", event);
+        }
+    });
+
+    spawn_playback_monitor(app.clone(), tx.clone());
+    tx
+}
+
+#[derive(Default)]
+struct PlaybackTick {
+    progress: Option<f32>,
+    started: Option<LoadedTrack>,
+    ended: Vec<(LoadedTrack, EndReason)>,
+    /// Generation and position of the playing track, unless the sink is paused.
+    playing: Option<(u64, Duration)>,
+    preload: Option<Preload>,
+}
+
+/// Notices the playing track's position standing still while the sink is not paused.
+struct StallWatch {
+    last: Option<(u64, Duration)>,
+    still_since: Instant,
+    stalled: Option<u64>,
+}
+
+impl StallWatch {
+    fn new() -> Self {
+        StallWatch {
+            last: None,
+            still_since: Instant::now(),
+            stalled: None,
+        }
+    }
+
+    fn update(&mut self, playing: Option<(u64, Duration)>) -> Option<PlaybackEvent> {
+        let now = Instant::now();
+        let Some(current) = playing else {
+            self.last = None;
+            self.still_since = now;
+            return None;
+        };
+        if self.last != Some(current) {
+            self.last = Some(current);
+            self.still_since = now;
+            return self
+                .stalled
+                .take()
+                .map(|generation| PlaybackEvent::Resumed { generation });
+        }
+        if self.stalled.is_none() && now - self.still_since >= STALL_THRESHOLD {
+            self.stalled = Some(current.0);
+            return Some(PlaybackEvent::Stalled {
+                generation: current.0,
+            });
+        }
+        None
+    }
+}
+
+struct Preload {
+    generation: u64,
+    index: usize,
+    item: QueueItem,
+}
+
+/// Retires tracks the sink has finished and decides whether the next queue entry should be loaded.
+fn poll_tracks(state: &AudioState) -> Option<PlaybackTick> {
+    let mut sink_guard = state.sink.lock().unwrap();
+    let mut tracks = state.tracks.lock().unwrap();
+    let crossfade = *state.crossfade.lock().unwrap();
+    let mut tick = PlaybackTick::default();
+
+    state.outgoing.lock().unwrap().retain(|s| !s.empty());
+
+    let paused = sink_guard.as_ref()?.is_paused();
+    // Media time left plays out this much faster, whether stretched or resampled
+    let speed = state.tempo.speed().max(MIN_SPEED);
+
+    let mut advanced = false;
+    while tracks.current().is_some_and(|t| t.control.is_finished()) {
+        if let Some(track) = tracks.tracks.pop_front() {
+            let failed = track
+                .stream
+                .as_ref()
+                .is_some_and(|s| s.failed.load(Ordering::Relaxed));
+            let reason = match (track.control.is_cancelled(), failed) {
+                (true, _) => EndReason::Skipped,
+                (false, true) => EndReason::Error,
+                (false, false) => EndReason::Finished,
+            };
+            tick.ended.push((track, reason));
+        }
+        advanced = true;
+    }
+    let position = tracks.current().map(|t| t.position()).unwrap_or_default();
+
+    if tracks.pending.is_some() {
+        let has_current = tracks.current().is_some();
+        let remaining = tracks
+            .current()
+            .and_then(|t| t.duration())
+            .map(|d| d.saturating_sub(position).div_f32(speed));
+
+        match (has_current, remaining, crossfade.overlap()) {
+            (true, Some(remaining), Some(overlap)) => {
+                if remaining <= overlap && !paused {
+                    let faded = start_crossfade(
+                        state,
+                        &mut sink_guard,
+                        &mut tracks,
+                        remaining,
+                        crossfade.curve,
+                    );
+                    tick.ended.extend(faded.map(|track| (track, EndReason::Finished)));
+                    advanced = true;
+                }
+            }
+            // Crossfade was turned off or the current track ended early, fall back to gapless
+            _ => {
+                if let Some(sink) = sink_guard.as_ref() {
+                    tracks.promote_pending(sink);
+                    advanced |= !has_current;
+                }
+            }
+        }
+    }
+
+    if advanced {
+        if let Some(track) = tracks.current() {
+            state.queue.lock().unwrap().set_current(track.index);
+            tick.started = Some(track.clone());
+        }
+    }
+
+    let sink = sink_guard.as_ref()?;
+    if let (false, Some(current)) = (sink.is_paused(), tracks.current()) {
+        let position = current.position();
+        tick.progress = Some(position.as_secs_f32());
+        tick.playing = Some((current.playback, position));
+    }
+
+    if tracks.tracks.len() < 2 && tracks.pending.is_none() && !tracks.preloading {
+        let window = PRELOAD_WINDOW + crossfade.overlap().unwrap_or_default();
+        let due = tracks.current().is_none_or(|current| {
+            current
+                .duration()
+                .is_none_or(|d| d.saturating_sub(current.position()).div_f32(speed) <= window)
+        });
+        let mut queue = state.queue.lock().unwrap();
+        let next = tracks.tail.and_then(|tail| queue.next_index(tail));
+        if let (true, Some(index)) = (due, next) {
+            if let Some(item) = queue.get(index) {
+                tracks.preloading = true;
+                tick.preload = Some(Preload {
+                    generation: tracks.generation,
+                    index,
+                    item: item.clone(),
+                });
+            }
+        }
+    }
+
+    Some(tick)
+}
+
+/// Fades the current track out on its own sink while the pending one fades in on a fresh sink.
+/// Returns the track being faded out.
+fn start_crossfade(
+    state: &AudioState,
+    sink_guard: &mut Option<Sink>,
+    tracks: &mut LoadedTracks,
+    overlap: Duration,
+    curve: FadeCurve,
+) -> Option<LoadedTrack> {
+    let (Some(pending), Some(outgoing)) = (tracks.pending.take(), sink_guard.take()) else {
+        return None;
+    };
+
+    // Nothing else is appended behind the current track while one is pending
+    let faded = tracks.tracks.pop_front();
+    if let Some(current) = &faded {
+        current.fade.fade_out(overlap, curve);
+    }
+
+    let sink = Sink::connect_new(&state.stream);
+    sink.set_volume(outgoing.volume());
+    sink.set_speed(outgoing.speed());
+    let fade = FadeControl::default();
+    fade.fade_in(overlap, curve);
+    tracks.push(&sink, pending.index, &pending.item, pending.opened, fade);
+    sink.play();
+
+    *sink_guard = Some(sink);
+    state.outgoing.lock().unwrap().push(outgoing);
+    faded
+}
+
+fn spawn_playback_monitor<R: Runtime>(app: tauri::AppHandle<R>, tx: mpsc::Sender<PlaybackEvent>) {
+    tokio::spawn(async move {
+        let mut stall = StallWatch::new();
+        loop {
+            let tick = {
+                let state = app.state::<AudioState>();
+                poll_tracks(&state)
+            };
+
+            if let Some(event) = stall.update(tick.as_ref().and_then(|t| t.playing)) {
+                let _ = tx.send(event).await;
+            }
+            if let Some(tick) = tick {
+                for (track, reason) in tick.ended {
+                    announce_end(&tx, &track, reason).await;
+                }
+                if let Some(track) = &tick.started {
+                    announce_track(&app, &tx, track).await;
+                }
+                if let Some(preload) = tick.preload {
+                    tokio::spawn(preload_track(app.clone(), tx.clone(), preload));
+                }
+                if let Some(progress) = tick.progress {
+                    let _ = tx.send(PlaybackEvent::UpdateProgress { progress }).await;
+                }
+            }
+
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    });
+}
+
+async fn announce_track<R: Runtime>(
+    app: &tauri::AppHandle<R>,
+    tx: &mpsc::Sender<PlaybackEvent>,
+    track: &LoadedTrack,
+) {
+    let _ = tx
+        .send(PlaybackEvent::TrackChanged {
+            index: track.index,
+            id: track.id.clone(),
+        })
+        .await;
+    let _ = tx
+        .send(PlaybackEvent::TrackStarted {
+            generation: track.playback,
+            id: track.id.clone(),
+        })
+        .await;
+
+    if let Some(stream) = &track.stream {
+        let duration = *stream.duration.lock().unwrap();
+        if let Some(duration) = duration {
+            let _ = app.emit("update_duration", duration.as_secs_f64());
+        }
+    }
+}
+
+async fn announce_end(tx: &mpsc::Sender<PlaybackEvent>, track: &LoadedTrack, reason: EndReason) {
+    let _ = tx
+        .send(PlaybackEvent::TrackEnded {
+            generation: track.playback,
+            id: track.id.clone(),
+            reason,
+        })
+        .await;
+}
+
+/// Opens the next queue entry and appends it behind the current track for gapless playback,
+/// or holds it back when it is going to be crossfaded in.
+async fn preload_track<R: Runtime>(
+    app: tauri::AppHandle<R>,
     tx: mpsc::Sender<PlaybackEvent>,
-    _app: tauri::AppHandle<R>,
+    preload: Preload,
 ) {
+    let state = app.state::<AudioState>();
+    let opened = open_track(&app, &state, &preload.item).await;
+
+    let (started, error) = {
+        let sink_guard = state.sink.lock().unwrap();
+        let mut tracks = state.tracks.lock().unwrap();
+        if tracks.generation != preload.generation {
+            return;
+        }
+        tracks.preloading = false;
+
+        let crossfade = state.crossfade.lock().unwrap().overlap().is_some();
+        let holds_for_crossfade =
+            crossfade && tracks.current().is_some_and(|t| t.duration().is_some());
+
+        match (opened, sink_guard.as_ref()) {
+            (Ok(opened), Some(_)) if holds_for_crossfade => {
+                tracks.pending = Some(PendingTrack {
+                    index: preload.index,
+                    item: preload.item,
+                    opened,
+                });
+                tracks.tail = Some(preload.index);
+                (None, None)
+            }
+            (Ok(opened), Some(sink)) => {
+                let starts_now = tracks.current().is_none();
+                tracks.push(sink, preload.index, &preload.item, opened, FadeControl::default());
+                if starts_now {
+                    state.queue.lock().unwrap().set_current(preload.index);
+                    (tracks.current().cloned(), None)
+                } else {
+                    (None, None)
+                }
+            }
+            (Err(e), _) => {
+                // Skip entries that cannot be opened instead of retrying them forever
+                tracks.tail = Some(preload.index);
+                let error = PlaybackEvent::PlaybackError {
+                    generation: tracks.next_playback(),
+                    id: preload.item.id.clone(),
+                    error: e.to_string(),
+                };
+                (None, Some(error))
+            }
+            _ => (None, None),
+        }
+    };
+
+    if let Some(error) = error {
+        let _ = tx.send(error).await;
+    }
+    if let Some(track) = started {
+        announce_track(&app, &tx, &track).await;
+    }
+}
+
+/// DSP stages shared by every track, applied between the decoder and the sink. Also returns
+/// the clock the time stretch stage keeps the track's media position on.
+fn processing_chain<S>(state: &AudioState, source: S) -> (BoxedSource, Arc<MediaClock>)
+where
+    S: Source<Item = i16> + Send + 'static,
+{
+    let clock = Arc::new(MediaClock::default());
+    let equalizer = Equalizer::new(source, state.equalizer.clone());
+    let stretch = TimeStretch::new(equalizer, state.tempo.clone(), clock.clone());
+    let pitch = PitchShift::new(stretch, state.tempo.clone());
+    let channels = ChannelMixer::new(pitch, state.channels.clone());
+    (Box::new(TransportFader::new(channels, state.transport.clone())), clock)
+}
+
+fn open_local_file(state: &AudioState, file_path: &str) -> std::result::Result<OpenedTrack, AppError> {
+    let path = Path::new(file_path);
+    if !path.exists() {
+        return Err(AppError::FileNotFound(file_path.to_string()));
+    }
+
+    let file = File::open(path).map_err(|e| AppError::FileOpenError(e.to_string()))?;
+    let buf_reader = BufReader::new(file);
+
+    let source = Decoder::new(buf_reader).map_err(|e| AppError::DecodeError(e.to_string()))?;
+    let duration = source.total_duration();
+    let source: DecodedSource = match normalization_gain(state, path) {
+        Some(gain) => Box::new(Normalizer { inner: source, gain }),
+        None => Box::new(source),
+    };
+
+    let (source, clock) = processing_chain(state, source);
+    Ok(OpenedTrack {
+        source,
+        clock,
+        duration,
+        stream: None,
+    })
+}
+
+/// Requests `url` from byte `offset` on, along with the offset the response actually starts at.
+async fn request_range(
+    client: &Requester,
+    url: &str,
+    offset: u64,
+) -> Option<(reqwest::Response, u64)> {
+    let response = client
+        .get(url)
+        .header(reqwest::header::RANGE, format!("bytes={}-", offset))
+        .send()
+        .await
+        .ok()?;
+
+    match response.status() {
+        reqwest::StatusCode::PARTIAL_CONTENT => Some((response, offset)),
+        // The server ignored the range and sent the whole file
+        status if status.is_success() => Some((response, 0)),
+        _ => None,
+    }
+}
+
+/// Retries a seek that was parked because its target had not been downloaded yet. Runs off the
+/// async runtime since `Sink::try_seek` waits for the audio thread, which may itself be waiting
+/// for the download.
+fn retry_pending_seek<R: Runtime>(
+    app: &tauri::AppHandle<R>,
+    stream: &Arc<StreamBuffer>,
+    tx: &mpsc::Sender<PlaybackEvent>,
+) {
+    let app = app.clone();
+    let stream = stream.clone();
+    let tx = tx.clone();
+    tokio::task::spawn_blocking(move || {
+        let state = app.state::<AudioState>();
+        let Some(target) = *state.seek_target.lock().unwrap() else {
+            return;
+        };
+        if !state.tracks.lock().unwrap().is_current_stream(&stream) {
+            return;
+        }
+
+        let seeked = state
+            .sink
+            .lock()
+            .unwrap()
+            .as_ref()
+            .is_some_and(|s| s.try_seek(target).is_ok());
+        // A target past the end of the stream will never become reachable
+        if seeked || stream.is_ended.load(Ordering::Relaxed) {
+            *state.seek_target.lock().unwrap() = None;
+            let _ = tx.blocking_send(PlaybackEvent::BufferSeekReady);
+        }
+    });
+}
+
+/// Reads the duration from the first bytes of a stream, announcing it if the stream is playing.
+fn probe_stream_duration<R: Runtime>(
+    app: &tauri::AppHandle<R>,
+    stream: &Arc<StreamBuffer>,
+    head: &[u8],
+) {
+    let Ok(probe) = Probe::new(Cursor::new(head)).guess_file_type() else {
+        return;
+    };
+    let Ok(tagged_file) = probe.read() else {
+        return;
+    };
+
+    let duration = tagged_file.properties().duration();
+    if duration.is_zero() {
+        return;
+    }
+    *stream.duration.lock().unwrap() = Some(duration);
+
+    // Preloaded streams announce their duration once they become current
+    let state = app.state::<AudioState>();
+    let is_current = state.tracks.lock().unwrap().is_current_stream(stream);
+    if is_current {
+        let _ = app.emit("update_duration", duration.as_secs_f64());
+    }
+}
+
+async fn open_url_stream<R: Runtime>(
+    app: &tauri::AppHandle<R>,
+    state: &AudioState,
+    url: &str,
+    request: &RequestOptions,
+    cache_key: &str,
+) -> std::result::Result<OpenedTrack, AppError> {
+    let tx = ensure_event_loop(app, state);
+    let cached = state.cache.lock().unwrap().lookup(cache_key);
+    let memory_limit = state.buffering.lock().unwrap().memory_limit();
+
+    // Fully cached tracks play straight from disk
+    if let Some(cached) = cached.as_ref().filter(|cached| cached.is_complete()) {
+        let stream = Arc::new(StreamBuffer::new(Some(cached.length), Overflow::Spill(memory_limit)));
+        if cached.attach(&stream).is_ok() {
+            stream.is_ended.store(true, Ordering::Relaxed);
+            let mut head = vec![0; PROBE_BYTES];
+            let read = stream.read_at(0, &mut head).unwrap_or(0);
+            probe_stream_duration(app, &stream, &head[..read]);
+
+            let (source, clock) = processing_chain(state, StreamingSource::new(stream.clone()));
+            return Ok(OpenedTrack {
+                source,
+                clock,
+                duration: None,
+                stream: Some(stream),
+            });
+        }
+    }
+
+    let client = state.http.requester(request)?;
+    let response = client.get(url).send().await?;
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok());
+    if hls::is_playlist(response.url(), content_type) {
+        return open_hls_stream(app, state, client, response).await;
+    }
+    let content_length = response.content_length().unwrap_or(0);
+    let accepts_ranges = content_length > 0
+        && response
+            .headers()
+            .get(reqwest::header::ACCEPT_RANGES)
+            .is_some_and(|value| value.as_bytes() == b"bytes");
+    let radio = icy::is_radio(response.headers());
+    drop(response);
+
+    let stream = Arc::new(StreamBuffer::new(
+        (content_length > 0).then_some(content_length),
+        Overflow::Spill(memory_limit),
+    ));
+
+    // Pick up whatever an earlier play of this track left in the cache
+    if let Some(cached) = cached.filter(|cached| cached.length == content_length) {
+        let _ = cached.attach(&stream);
+    }
+    let mut cache_writer = if content_length > 0 {
+        CacheWriter::open(&state.cache, cache_key, content_length)
+    } else {
+        None
+    };
+
+    // Radio and other endless streams ignore the range, so they are not probed at all
+    if !radio && content_length > 0 {
+        tokio::spawn({
+            let client = client.clone();
+            let url = url.to_string();
+            let app = app.clone();
+            let stream = stream.clone();
+
+            async move {
+                let head = async {
+                    let response = client
+                        .get(&url)
+                        .header("Range", format!("bytes=0-{}", PROBE_BYTES - 1))
+                        .send()
+                        .await?;
+                    let mut body = response.bytes_stream();
+                    let mut head = Vec::with_capacity(PROBE_BYTES);
+                    while head.len() < PROBE_BYTES {
+                        match body.next().await {
+                            Some(chunk) => head.extend_from_slice(&chunk?),
+                            None => break,
+                        }
+                    }
+                    head.truncate(PROBE_BYTES);
+                    Ok::<_, reqwest::Error>(head)
+                };
+                tokio::select! {
+                    head = head => {
+                        if let Ok(head) = head {
+                            probe_stream_duration(&app, &stream, &head);
+                        }
+                    }
+                    _ = stream.cancelled() => {}
+                }
+            }
+        });
+    }
+
+    // Create a new request for streaming, resuming after the cached bytes where possible
+    let resume_at = stream.next_missing(0);
+    let response = if accepts_ranges && resume_at > 0 {
+        request_range(&client, url, resume_at)
+            .await
+            .ok_or_else(|| AppError::NetworkError("Failed to resume download".to_string()))?
+    } else {
+        let request = client.get(url).header(icy::REQUEST_HEADER.0, icy::REQUEST_HEADER.1);
+        (request.send().await?, 0)
+    };
+    let mut icy = IcyDemuxer::from_headers(response.0.headers());
+    if icy::is_radio(response.0.headers()) {
+        // Radio never ends and cannot seek back, so played bytes are just dropped
+        stream.set_overflow(Overflow::Discard(memory_limit));
+    }
+
+    let download_app = app.clone();
+    let download_stream = stream.clone();
+    let url = url.to_string();
+
+    // Spawn streaming task, which runs until the stream is complete or cancelled
     tokio::spawn(async move {
+        let mut response = Some(response);
+        let mut offset = 0;
+        let mut current_size = 0;
+        let mut next_seek_retry = SEEK_RETRY_BYTES;
+        let mut error = None;
+        let mut stripped = Vec::new();
+        let mut title = None;
+
         loop {
-            let progress = {
-                let guard = sink_ref.lock().unwrap();
-                if let Some(sink) = guard.as_ref() {
-                    if !sink.is_paused() {
-                        Some(sink.get_pos().as_secs_f32())
-                    } else {
-                        None
+            let (response, start) = match response.take() {
+                Some(response) => response,
+                None => {
+                    // Only the first request asks for interleaved metadata
+                    icy = None;
+                    tokio::select! {
+                        response = request_range(&client, &url, offset) => match response {
+                            Some(response) => response,
+                            None => {
+                                error = Some("Failed to resume download".to_string());
+                                break;
+                            }
+                        },
+                        _ = download_stream.cancelled() => break,
+                    }
+                }
+            };
+            offset = start;
+            let mut bytes_stream = response.bytes_stream();
+            let mut received = 0;
+            let mut jump_to = None;
+
+            loop {
+                tokio::select! {
+                    chunk = bytes_stream.next() => {
+                        let chunk = match chunk {
+                            Some(Ok(chunk)) => chunk,
+                            Some(Err(e)) => {
+                                error = Some(e.to_string());
+                                break;
+                            }
+                            None => break,
+                        };
+                        let data: &[u8] = match &mut icy {
+                            Some(icy) => {
+                                stripped.clear();
+                                let announced = icy.push(&chunk, &mut stripped);
+                                if announced.is_some() && announced != title {
+                                    title = announced.clone();
+                                    if let Some(announced) = announced {
+                                        announce_stream_title(&download_app, &download_stream, &tx, announced).await;
+                                    }
+                                }
+                                &stripped
+                            }
+                            None => &chunk,
+                        };
+                        download_stream.write(offset, data);
+                        if let Some(error) = download_stream.take_spill_error() {
+                            report_stream_failure(&download_app, &download_stream, &tx, error).await;
+                        }
+                        if let Some(writer) = &mut cache_writer {
+                            writer.write(offset, data);
+                        }
+                        offset += data.len() as u64;
+                        received += data.len();
+                        current_size += data.len();
+
+                        // Send buffer progress, which the frontend shows for the current track only
+                        let is_current = content_length > 0
+                            && download_app
+                                .state::<AudioState>()
+                                .tracks
+                                .lock()
+                                .unwrap()
+                                .is_current_stream(&download_stream);
+                        if is_current {
+                            let progress = download_stream.buffered_bytes() as f32 / content_length as f32;
+                            let _ = tx.send(PlaybackEvent::BufferUpdate { buffer_progress: progress }).await;
+                        }
+
+                        download_stream.notify_data();
+
+                        if current_size >= next_seek_retry {
+                            next_seek_retry = current_size + SEEK_RETRY_BYTES;
+                            retry_pending_seek(&download_app, &download_stream, &tx);
+                        }
+
+                        // Ran into bytes fetched by an earlier request, skip past them
+                        let missing = download_stream.next_missing(offset);
+                        if accepts_ranges && missing != offset {
+                            jump_to = Some(missing);
+                            break;
+                        }
                     }
-                } else {
-                    break;
+                    _ = download_stream.wanted_changed.notified(), if accepts_ranges => {
+                        let Some(wanted) = download_stream.take_wanted() else {
+                            continue;
+                        };
+                        let arriving_soon = (offset..offset + RANGE_JUMP_BYTES).contains(&wanted);
+                        if !arriving_soon && download_stream.next_missing(wanted) == wanted {
+                            jump_to = Some(wanted);
+                            // Give a parked seek a quick chance once the new range starts arriving
+                            next_seek_retry = current_size + SEEK_RETRY_BYTES / 4;
+                            break;
+                        }
+                    }
+                    _ = download_stream.cancelled() => break,
                 }
-            };  // guard is dropped here
-
-            if let Some(progress) = progress {
-                let _ = tx.send(PlaybackEvent::UpdateProgress { progress }).await;
             }
 
-            tokio::time::sleep(Duration::from_millis(100)).await;
+            if let Some(writer) = &cache_writer {
+                writer.save();
+            }
+            if download_stream.is_cancelled() {
+                return;
+            }
+            if !accepts_ranges || download_stream.is_complete() || (received == 0 && jump_to.is_none()) {
+                break;
+            }
+            // Once the tail is done, go back for whatever was skipped
+            offset = jump_to
+                .filter(|&position| position < content_length)
+                .unwrap_or_else(|| download_stream.next_missing(0));
         }
-    });
-}
-
-#[tauri::command]
-pub async fn play_url_stream<R: Runtime>(
-    app: tauri::AppHandle<R>,
-    state: State<'_, AudioState>, 
-    url: String
-) -> std::result::Result<(), AppError> {
-    // Stop and clean up previous playback instance
-    {
-        let sink = {
-            let mut sink_guard = state.sink.lock().unwrap();
-            sink_guard.take()
-        };
-        
-        if let Some(sink) = sink {
-            sink.stop();
-            // Wait briefly to ensure resource release
-            tokio::time::sleep(Duration::from_millis(100)).await;
+        // A stream of unknown length cannot tell a cut-off from its end
+        let failed = error.filter(|_| {
+            download_stream.length.is_none() || !download_stream.is_complete()
+        });
+        if failed.is_some() {
+            download_stream.failed.store(true, Ordering::Relaxed);
         }
-    }
+        download_stream.is_ended.store(true, Ordering::Relaxed);
+        // Wake a reader blocked on the tail so it can observe the end of the stream
+        download_stream.notify_data();
+        retry_pending_seek(&download_app, &download_stream, &tx);
+        if let Some(error) = failed {
+            report_stream_failure(&download_app, &download_stream, &tx, error).await;
+        }
+    });
 
-    // Clear buffer and reset state
-    {
-        let mut buffer = state.buffer.lock().unwrap();
-        buffer.clear();
-        state.is_stream_ended.store(false, Ordering::Relaxed);
-        *state.decoder.lock().unwrap() = None;
-        *state.seek_target.lock().unwrap() = None;
-        *state.buffered_duration.lock().unwrap() = Duration::from_secs(0);
-    }
+    let (source, clock) = processing_chain(state, StreamingSource::new(stream.clone()));
+    Ok(OpenedTrack {
+        source,
+        clock,
+        duration: None,
+        stream: Some(stream),
+    })
+}
 
-    let client = reqwest::Client::new();
-    
-    let content_length = client.get(&url)
-        .send()
-        .await?
-        .content_length()
-        .unwrap_or(0);
+/// Plays an HLS playlist, its segments decoded as one continuous stream.
+async fn open_hls_stream<R: Runtime>(
+    app: &tauri::AppHandle<R>,
+    state: &AudioState,
+    client: Requester,
+    response: reqwest::Response,
+) -> std::result::Result<OpenedTrack, AppError> {
+    let tx = ensure_event_loop(app, state);
+    let memory_limit = state.buffering.lock().unwrap().memory_limit();
+    let (source, loader) = hls::open(client, response, memory_limit).await?;
+    let stream = source.stream();
 
     tokio::spawn({
-        let client = reqwest::Client::new();
-        let url = url.clone();
         let app = app.clone();
-        
+        let stream = stream.clone();
         async move {
-            if let Ok(response) = client.get(&url)
-                .header("Range", "bytes=0-65535")
-                .send()
-                .await
-            {
-                if let Ok(chunk) = response.bytes().await {
-                    let cursor = std::io::Cursor::new(chunk);
-                    
-                    if let Ok(probe) = Probe::new(cursor).guess_file_type() {
-                        if let Ok(tagged_file) = probe.read() {
-                            let duration = if content_length > 0 {
-                                let chunk_duration = tagged_file.properties().duration().as_secs_f64();
-                                
-                                chunk_duration * 1000.0
-                            } else {
-                                0.0
-                            };
-                            
-                            let _ = app.emit("update_duration", duration);
-                        }
-                    }
-                }
+            if let Err(e) = loader.run().await {
+                report_stream_failure(&app, &stream, &tx, e.to_string()).await;
             }
         }
     });
 
-    // Create a new request for streaming
-    let response = client.get(&url).send().await?;
-    
-    // Create event channel
-    let (tx, mut rx) = mpsc::channel(32);
-    let app_handle = app.clone();
-    
-    // Store sender for progress updates
-    *state.progress_tx.lock().unwrap() = Some(tx.clone());
-    
-    // Spawn event handler
-    tokio::spawn(async move {
-        while let Some(event) = rx.recv().await {
-            let _ = app_handle.emit("playback_event", event);
-        }
-    });
+    let (source, clock) = processing_chain(state, source);
+    Ok(OpenedTrack {
+        source,
+        clock,
+        duration: None,
+        stream: Some(stream),
+    })
+}
+
+/// Tells the frontend which loaded track lost its download, if it is still loaded.
+async fn report_stream_failure<R: Runtime>(
+    app: &tauri::AppHandle<R>,
+    stream: &Arc<StreamBuffer>,
+    tx: &mpsc::Sender<PlaybackEvent>,
+    error: String,
+) {
+    if let Some((generation, id, _)) = stream_track(app, stream) {
+        let _ = tx
+            .send(PlaybackEvent::PlaybackError {
+                generation,
+                id,
+                error,
+            })
+            .await;
+    }
+}
 
-    // Start progress update task using the new helper function
-    spawn_progress_task(state.sink.clone(), tx.clone(), app.clone());
+/// Announces a new title from a radio stream, and shows it in the system media controls
+/// while the stream is playing.
+async fn announce_stream_title<R: Runtime>(
+    app: &tauri::AppHandle<R>,
+    stream: &Arc<StreamBuffer>,
+    tx: &mpsc::Sender<PlaybackEvent>,
+    title: String,
+) {
+    let Some((generation, _, is_current)) = stream_track(app, stream) else {
+        return;
+    };
+    if is_current {
+        let _ = media_control::set_stream_title(&app.state::<MediaControlState>(), &title);
+    }
+    let _ = tx.send(PlaybackEvent::StreamTitle { generation, title }).await;
+}
 
-    // Take ownership of necessary state
-    let buffer = state.buffer.clone();
-    let is_stream_ended = state.is_stream_ended.clone();
-    let data_available = state.data_available.clone();
-    let seek_target = state.seek_target.clone();
-    let buffered_duration = state.buffered_duration.clone();
-    let tx_clone = tx.clone();
+/// Playback generation and id of the loaded track reading `stream`, and whether it is the
+/// current one.
+fn stream_track<R: Runtime>(
+    app: &tauri::AppHandle<R>,
+    stream: &Arc<StreamBuffer>,
+) -> Option<(u64, String, bool)> {
+    let state = app.state::<AudioState>();
+    let tracks = state.tracks.lock().unwrap();
+    tracks
+        .tracks
+        .iter()
+        .enumerate()
+        .find(|(_, t)| t.stream.as_ref().is_some_and(|s| Arc::ptr_eq(s, stream)))
+        .map(|(position, t)| (t.playback, t.id.clone(), position == 0))
+}
 
-    // Initialize sink and source
-    let mut sink_guard = state.sink.lock().unwrap();
-    if let Some(sink) = sink_guard.take() {
-        sink.stop();
+async fn open_track<R: Runtime>(
+    app: &tauri::AppHandle<R>,
+    state: &AudioState,
+    item: &QueueItem,
+) -> std::result::Result<OpenedTrack, AppError> {
+    match &item.source {
+        TrackSource::Local { path } => open_local_file(state, path),
+        TrackSource::Url { url, request } => {
+            open_url_stream(app, state, url, request, &item.id).await
+        }
     }
+}
+
+/// Replaces whatever is playing with the queue entry at `index` on a fresh sink.
+async fn start_queue_at<R: Runtime>(
+    app: &tauri::AppHandle<R>,
+    state: &AudioState,
+    index: usize,
+) -> std::result::Result<(), AppError> {
+    start_queue_with(app, state, index, StartOptions::default()).await
+}
 
+/// How a fresh start departs from just carrying on with the old sink's settings.
+#[derive(Default)]
+pub struct StartOptions {
+    pub volume: Option<f32>,
+    /// Speed the sink resamples by, as returned from `TempoControl::set`.
+    pub sink_speed: Option<f32>,
+    pub position: Option<Duration>,
+    pub paused: bool,
+}
+
+/// Replaces the queue and starts it at `start_index`.
+pub async fn start_queue<R: Runtime>(
+    app: &tauri::AppHandle<R>,
+    state: &AudioState,
+    items: Vec<QueueItem>,
+    start_index: usize,
+    options: StartOptions,
+) -> std::result::Result<(), AppError> {
     {
-        let mut buffer = buffer.lock().unwrap();
-        buffer.clear();
-        is_stream_ended.store(false, Ordering::Relaxed);
+        let mut queue = state.queue.lock().unwrap();
+        queue.replace(items);
+        queue.jump_to(start_index);
     }
-    *state.decoder.lock().unwrap() = None;
+    start_queue_with(app, state, start_index, options).await
+}
 
-    let sink = Sink::connect_new(&state.stream);
-    let streaming_source = StreamingSource::new(
-        buffer.clone(),
-        is_stream_ended.clone(),
-        state.decoder.clone(),
-        data_available.clone(),
-    );
-    sink.append(streaming_source);
-    
-    // Spawn streaming task
-    let _stream_handle = tokio::spawn(async move {
-        let mut bytes_stream = response.bytes_stream();
-        let mut current_size = 0;
+/// Replaces the queue and starts it at `start_index` with the sink volume at zero, for
+/// callers that ramp it up themselves.
+pub async fn start_queue_muted<R: Runtime>(
+    app: &tauri::AppHandle<R>,
+    state: &AudioState,
+    items: Vec<QueueItem>,
+    start_index: usize,
+) -> std::result::Result<(), AppError> {
+    let options = StartOptions {
+        volume: Some(0.0),
+        ..StartOptions::default()
+    };
+    start_queue(app, state, items, start_index, options).await
+}
 
-        while let Some(chunk) = bytes_stream.next().await {
-            if let Ok(data) = chunk {
-                current_size += data.len();
+/// Fades out the track playing on `sink` and leaves the sink to run out among the outgoing
+/// ones, rather than cutting it off mid-waveform.
+/// Returns the track that was playing.
+fn stop_with_fade(
+    state: &AudioState,
+    sink: Sink,
+    tracks: &mut LoadedTracks,
+) -> Option<LoadedTrack> {
+    let fade = state.transport.pause_duration();
+    let stopped = tracks.current().cloned();
+    let current = if sink.is_paused() || fade.is_zero() {
+        None
+    } else {
+        tracks.tracks.pop_front()
+    };
+    tracks.reset();
 
-                // Update buffer in separate scope
-                {
-                    let mut buffer = buffer.lock().unwrap();
-                    buffer.extend_from_slice(&data);
-                }
+    let mut outgoing = state.outgoing.lock().unwrap();
+    // Paused sinks would otherwise linger until the next resume
+    outgoing.retain(|s| !s.is_paused());
+    match current {
+        Some(current) => {
+            current.fade.fade_out(fade, FadeCurve::Linear);
+            outgoing.push(sink);
+        }
+        None => sink.stop(),
+    }
+    stopped
+}
 
-                // Send buffer progress
-                if content_length > 0 {
-                    let progress = current_size as f32 / content_length as f32;
-                    let _ = tx_clone.send(PlaybackEvent::BufferUpdate { buffer_progress: progress }).await;
-                }
+/// Like `start_queue_at`, with `options` overriding what carries over from the old sink.
+async fn start_queue_with<R: Runtime>(
+    app: &tauri::AppHandle<R>,
+    state: &AudioState,
+    index: usize,
+    options: StartOptions,
+) -> std::result::Result<(), AppError> {
+    let tx = ensure_event_loop(app, state);
+    let item = state.queue.lock().unwrap().get(index).cloned().ok_or_else(|| {
+        AppError::InvalidOperation("Queue index out of range".to_string())
+    })?;
 
-                // Check seek target in separate scope
-                let should_send_ready = {
-                    if let Some(target) = *seek_target.lock().unwrap() {
-                        let buffered = *buffered_duration.lock().unwrap();
-                        buffered >= target
-                    } else {
-                        false
-                    }
-                };
+    // Stop and clean up previous playback instance, keeping its volume and speed
+    let (start, volume, speed, stopped) = {
+        let mut sink_guard = state.sink.lock().unwrap();
+        let mut tracks = state.tracks.lock().unwrap();
+        let start = tracks.next_start();
+        let (volume, speed) =
+            sink_guard.as_ref().map_or((1.0, 1.0), |s| (s.volume(), s.speed()));
+        let stopped = match sink_guard.take() {
+            Some(sink) => stop_with_fade(state, sink, &mut tracks),
+            None => {
+                tracks.reset();
+                None
+            }
+        };
+        (start, volume, speed, stopped)
+    };
+    if let Some(track) = stopped {
+        announce_end(&tx, &track, EndReason::Skipped).await;
+    }
+    if options.paused {
+        // The resume then fades in from silence
+        state.transport.fade_out(Duration::ZERO);
+    } else {
+        // Undoes a pause still fading out, the new track starts at full gain
+        state.transport.fade_in(state.transport.pause_duration());
+    }
 
-                if should_send_ready {
-                    let _ = tx_clone.send(PlaybackEvent::BufferSeekReady).await;
-                    *seek_target.lock().unwrap() = None;
-                }
+    let mut opened = match open_track(app, state, &item).await {
+        Ok(opened) => opened,
+        Err(e) => {
+            let generation = state.tracks.lock().unwrap().next_playback();
+            let _ = tx
+                .send(PlaybackEvent::PlaybackError {
+                    generation,
+                    id: item.id.clone(),
+                    error: e.to_string(),
+                })
+                .await;
+            return Err(e);
+        }
+    };
+    // Streams that have not got there yet are seeked once the download catches up
+    let parked = options
+        .position
+        .filter(|&position| opened.source.try_seek(position).is_err());
 
-                // Signal new data in separate scope
-                {
-                    let (lock, cvar) = &*data_available;
-                    let mut available = lock.lock().unwrap();
-                    *available = true;
-                    cvar.notify_one();
-                }
-            }
+    let (track, stopped) = {
+        let mut sink_guard = state.sink.lock().unwrap();
+        let mut tracks = state.tracks.lock().unwrap();
+        // A later start took over while this track was opening. Dropping it here cancels
+        // its download before anything from it reaches the sink.
+        if tracks.starts != start {
+            return Ok(());
         }
-        is_stream_ended.store(true, Ordering::Relaxed);
-    });
+        // A seek still parked for the previous track must not land on this one
+        *state.seek_target.lock().unwrap() = parked;
+        let stopped = sink_guard
+            .take()
+            .and_then(|sink| stop_with_fade(state, sink, &mut tracks));
 
-    // Start playback
-    sink.play();
-    *sink_guard = Some(sink);
+        let sink = Sink::connect_new(&state.stream);
+        sink.set_volume(options.volume.unwrap_or(volume));
+        sink.set_speed(options.sink_speed.unwrap_or(speed));
+        if options.paused {
+            sink.pause();
+        }
+        tracks.push(&sink, index, &item, opened, FadeControl::default());
+        if !options.paused {
+            sink.play();
+        }
+        *sink_guard = Some(sink);
+        state.queue.lock().unwrap().set_current(index);
+        (tracks.current().cloned(), stopped)
+    };
+
+    if let Some(track) = stopped {
+        announce_end(&tx, &track, EndReason::Skipped).await;
+    }
+    if let Some(track) = track {
+        announce_track(app, &tx, &track).await;
+    }
 
     Ok(())
 }
 
+#[tauri::command]
+pub async fn play_url_stream<R: Runtime>(
+    app: tauri::AppHandle<R>,
+    state: State<'_, AudioState>, 
+    url: String,
+    request: Option<RequestOptions>,
+) -> std::result::Result<(), AppError> {
+    state.queue.lock().unwrap().replace(vec![QueueItem {
+        id: url.clone(),
+        source: TrackSource::Url {
+            url,
+            request: request.unwrap_or_default(),
+        },
+        artist: None,
+        album: None,
+    }]);
+    start_queue_at(&app, &state, 0).await
+}
+
 #[tauri::command]
 pub async fn play_local_file<R: Runtime>(
     app: tauri::AppHandle<R>,
@@ -439,71 +1757,207 @@ pub async fn play_local_file<R: Runtime>(
         return Err(AppError::FileNotFound(file_path));
     }
 
-    let mut sink_guard = state.sink.lock().unwrap();
-    if let Some(sink) = sink_guard.take() {
-        sink.stop();
-    }
+    state.queue.lock().unwrap().replace(vec![QueueItem {
+        id: file_path.clone(),
+        source: TrackSource::Local { path: file_path },
+        artist: None,
+        album: None,
+    }]);
+    start_queue_at(&app, &state, 0).await
+}
 
-    let file = File::open(&file_path).map_err(|e| AppError::FileOpenError(e.to_string()))?;
-    let buf_reader = BufReader::new(file);
+#[tauri::command]
+pub async fn set_queue<R: Runtime>(
+    app: tauri::AppHandle<R>,
+    state: State<'_, AudioState>,
+    items: Vec<QueueItem>,
+    start_index: usize,
+) -> std::result::Result<(), AppError> {
+    start_queue(&app, &state, items, start_index, StartOptions::default()).await
+}
 
-    let source = Decoder::new(buf_reader).map_err(|e| AppError::DecodeError(e.to_string()))?;
+#[tauri::command]
+pub async fn play_queue_index<R: Runtime>(
+    app: tauri::AppHandle<R>,
+    state: State<'_, AudioState>,
+    index: usize,
+) -> std::result::Result<(), AppError> {
+    state.queue.lock().unwrap().jump_to(index);
+    start_queue_at(&app, &state, index).await
+}
 
-    let sink = Sink::connect_new(&state.stream);
-    sink.append(source);
+#[tauri::command]
+pub fn enqueue(state: State<AudioState>, items: Vec<QueueItem>) -> std::result::Result<(), AppError> {
+    state.queue.lock().unwrap().extend(items);
+    Ok(())
+}
 
-    // Create new event sender if needed
-    let mut tx_guard = state.progress_tx.lock().unwrap();
-    if tx_guard.is_none() {
-        let (tx, mut rx) = mpsc::channel(32);
-        *tx_guard = Some(tx.clone());
-
-        // Spawn event handler
-        let app_handle = app.clone();
-        tokio::spawn(async move {
-            while let Some(event) = rx.recv().await {
-                let _ = app_handle.emit("playback_event", event);
-            }
-        });
+#[tauri::command]
+pub fn clear_queue(state: State<AudioState>) -> std::result::Result<(), AppError> {
+    let mut tracks = state.tracks.lock().unwrap();
+    tracks.drop_preloaded();
+    tracks.tail = None;
+    state.queue.lock().unwrap().clear();
+    Ok(())
+}
 
-        // Start progress update task using the helper function
-        spawn_progress_task(state.sink.clone(), tx.clone(), app.clone());
-    }
+#[tauri::command]
+pub fn get_queue(state: State<AudioState>) -> std::result::Result<PlayQueue, AppError> {
+    Ok(state.queue.lock().unwrap().clone())
+}
 
-    *sink_guard = Some(sink);
+#[tauri::command]
+pub fn set_repeat_mode(state: State<AudioState>, mode: RepeatMode) -> std::result::Result<(), AppError> {
+    // Whatever was preloaded followed the old mode
+    let mut tracks = state.tracks.lock().unwrap();
+    tracks.drop_preloaded();
+    state.queue.lock().unwrap().set_repeat(mode);
+    Ok(())
+}
 
+#[tauri::command]
+pub fn set_shuffle_mode(state: State<AudioState>, mode: ShuffleMode) -> std::result::Result<(), AppError> {
+    let mut tracks = state.tracks.lock().unwrap();
+    tracks.drop_preloaded();
+    state.queue.lock().unwrap().set_shuffle(mode);
     Ok(())
 }
 
+#[tauri::command]
+pub async fn skip_next<R: Runtime>(
+    app: tauri::AppHandle<R>,
+    state: State<'_, AudioState>,
+) -> std::result::Result<(), AppError> {
+    // A preloaded next track can take over gaplessly by dropping the current one, unless it
+    // is a repeat of the current track that a manual skip has to leave behind
+    let (skipped, next) = {
+        let sink_guard = state.sink.lock().unwrap();
+        let mut tracks = state.tracks.lock().unwrap();
+        let next = {
+            let mut queue = state.queue.lock().unwrap();
+            queue.current_index().and_then(|i| queue.skip_index(i))
+        };
+        let loaded = tracks.tracks.get(1).map(|t| t.index)
+            .or(tracks.pending.as_ref().map(|p| p.index));
+        let skipped = match (sink_guard.as_ref(), tracks.current()) {
+            (Some(sink), Some(current)) if loaded.is_some() && loaded == next => {
+                current.control.cancel();
+                tracks.promote_pending(sink);
+                sink.play();
+                true
+            }
+            _ => false,
+        };
+        (skipped, next)
+    };
+    if skipped {
+        return Ok(());
+    }
+
+    match next {
+        Some(index) => start_queue_at(&app, &state, index).await,
+        None => Err(AppError::InvalidOperation("No next track in queue".to_string())),
+    }
+}
+
+#[tauri::command]
+pub async fn skip_previous<R: Runtime>(
+    app: tauri::AppHandle<R>,
+    state: State<'_, AudioState>,
+) -> std::result::Result<(), AppError> {
+    // Without an earlier entry the webview decides, as it did before the queue existed
+    let target = {
+        let queue = state.queue.lock().unwrap();
+        queue.current_index().and_then(|i| queue.previous_index(i))
+    };
+    match target {
+        Some(index) => start_queue_at(&app, &state, index).await,
+        None => Err(AppError::InvalidOperation("No previous track in queue".to_string())),
+    }
+}
+
 // Add explicit type parameters for all non-async commands
 #[tauri::command]
 pub fn get_music_status(state: State<AudioState>) -> std::result::Result<String, AppError> {
     let sink: std::sync::MutexGuard<Option<Sink>> = state.sink.lock().unwrap();
     Ok(match &*sink {
+        Some(s) if s.empty() => "Stopped".to_string(),
         Some(s) if s.is_paused() => "Paused".to_string(),
         Some(_) => "Playing".to_string(),
         None => "Stopped".to_string(),
     })
 }
 
-#[tauri::command]
-pub fn pause(state: State<AudioState>) -> std::result::Result<(), AppError> {
-    let sink = state.sink.lock().unwrap();
-    if let Some(s) = &*sink {
+/// Media position of the playing track.
+pub fn current_track_position(state: &AudioState) -> Option<Duration> {
+    state.tracks.lock().unwrap().current().map(|t| t.position())
+}
+
+/// Playback generation of the playing track, a new value each time one starts even when it
+/// repeats, along with the wall clock time it has left at the current speed.
+pub fn current_track_remaining(state: &AudioState) -> Option<(u64, Option<Duration>)> {
+    let tracks = state.tracks.lock().unwrap();
+    let current = tracks.current()?;
+    let speed = state.tempo.speed().max(MIN_SPEED);
+    let remaining = current
+        .duration()
+        .map(|d| d.saturating_sub(current.position()).div_f32(speed));
+    Some((current.playback, remaining))
+}
+
+fn pause_sinks(state: &AudioState) {
+    if let Some(s) = &*state.sink.lock().unwrap() {
         s.pause();
-        Ok(())
-    } else {
-        Err(AppError::InvalidOperation(
+        for outgoing in state.outgoing.lock().unwrap().iter() {
+            outgoing.pause();
+        }
+    }
+}
+
+/// Pauses straight away, for callers that already faded the volume out themselves.
+pub fn pause_now(state: &AudioState) {
+    state.transport.fade_out(Duration::ZERO);
+    pause_sinks(state);
+}
+
+/// Fades the output out and pauses the sink once it is silent.
+#[tauri::command]
+pub fn pause<R: Runtime>(
+    app: tauri::AppHandle<R>,
+    state: State<AudioState>,
+) -> std::result::Result<(), AppError> {
+    if state.sink.lock().unwrap().is_none() {
+        return Err(AppError::InvalidOperation(
             "No active playback to pause".to_string(),
-        ))
+        ));
+    }
+
+    let fade = state.transport.pause_duration();
+    let generation = state.transport.fade_out(fade);
+    if fade.is_zero() {
+        pause_sinks(&state);
+        return Ok(());
     }
+    tauri::async_runtime::spawn(async move {
+        tokio::time::sleep(fade).await;
+        let state = app.state::<AudioState>();
+        // A resume, seek or new track in the meantime takes over
+        if state.transport.is_current(generation) {
+            pause_sinks(&state);
+        }
+    });
+    Ok(())
 }
 
 #[tauri::command]
 pub fn resume(state: State<AudioState>) -> std::result::Result<(), AppError> {
     let sink = state.sink.lock().unwrap();
     if let Some(s) = &*sink {
+        state.transport.fade_in(state.transport.pause_duration());
         s.play();
+        for outgoing in state.outgoing.lock().unwrap().iter() {
+            outgoing.play();
+        }
         Ok(())
     } else {
         Err(AppError::InvalidOperation(
@@ -535,12 +1989,49 @@ pub fn get_volume(state: State<AudioState>) -> std::result::Result<f32, AppError
     }
 }
 
+/// Seeks the current track, fading out before the jump and back in after it while playing.
 #[tauri::command]
-pub fn set_playback_progress(
-    _app: tauri::AppHandle<impl Runtime>,
-    state: State<AudioState>, 
+pub async fn set_playback_progress(
+    app: tauri::AppHandle<impl Runtime>,
+    state: State<'_, AudioState>,
     progress: f32
 ) -> std::result::Result<(), AppError> {
+    let playing = match &*state.sink.lock().unwrap() {
+        Some(s) => !s.is_paused(),
+        None => {
+            return Err(AppError::InvalidOperation(
+                "No active playback to set progress".to_string(),
+            ))
+        }
+    };
+    let fade = state.transport.seek_duration();
+    if !playing || fade.is_zero() {
+        return seek_sink_blocking(&app, progress).await;
+    }
+
+    let generation = state.transport.fade_out(fade);
+    tokio::time::sleep(fade).await;
+    let result = seek_sink_blocking(&app, progress).await;
+    // Unless a pause came in, or something else already brought the gain back up
+    if state.transport.is_current(generation) {
+        state.transport.fade_in(fade);
+    }
+    result
+}
+
+/// `Sink::try_seek` waits for the audio thread to take the seek, so it runs off the runtime
+/// like in `retry_pending_seek`.
+async fn seek_sink_blocking<R: Runtime>(
+    app: &tauri::AppHandle<R>,
+    progress: f32,
+) -> std::result::Result<(), AppError> {
+    let app = app.clone();
+    tokio::task::spawn_blocking(move || seek_sink(&app.state::<AudioState>(), progress))
+        .await
+        .map_err(|e| AppError::SeekError(e.to_string()))?
+}
+
+fn seek_sink(state: &AudioState, progress: f32) -> std::result::Result<(), AppError> {
     let mut sink = state.sink.lock().unwrap();
     let duration = Duration::from_secs_f32(progress);
 
@@ -559,22 +2050,70 @@ pub fn set_playback_progress(
     }
 }
 
+/// Sets balance, mono downmix, channel swap and crossfeed, heard from the next frame on.
+#[tauri::command]
+pub fn set_channel_settings(
+    state: State<AudioState>,
+    settings: ChannelSettings,
+) -> std::result::Result<(), AppError> {
+    settings.validate()?;
+    state.channels.set(settings);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_channel_settings(
+    state: State<AudioState>,
+) -> std::result::Result<ChannelSettings, AppError> {
+    Ok(state.channels.settings())
+}
+
+#[tauri::command]
+pub fn set_transport_fades(
+    state: State<AudioState>,
+    settings: TransportFadeSettings,
+) -> std::result::Result<(), AppError> {
+    let range = 0.0..=MAX_TRANSPORT_FADE.as_secs_f32();
+    if !range.contains(&settings.pause) || !range.contains(&settings.seek) {
+        return Err(AppError::InvalidOperation(format!(
+            "Pause and seek fades must be between 0 and {} seconds",
+            MAX_TRANSPORT_FADE.as_secs_f32()
+        )));
+    }
+    state.transport.set_settings(settings);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_transport_fades(
+    state: State<AudioState>,
+) -> std::result::Result<TransportFadeSettings, AppError> {
+    Ok(state.transport.settings())
+}
+
 #[tauri::command]
 pub fn get_playback_progress(state: State<AudioState>) -> std::result::Result<f32, AppError> {
     let sink = state.sink.lock().unwrap();
+    let tracks = state.tracks.lock().unwrap();
 
-    if let Some(s) = &*sink {
-        Ok(s.get_pos().as_secs_f32())
-    } else {
-        Err(AppError::InvalidOperation("No active playback".to_string()))
+    match (&*sink, tracks.current()) {
+        (Some(_), Some(current)) => Ok(current.position().as_secs_f32()),
+        (Some(_), None) => Ok(0.0),
+        (None, _) => Err(AppError::InvalidOperation("No active playback".to_string())),
     }
 }
 
 #[tauri::command]
 pub fn set_speed(state: State<AudioState>, speed: f32) -> std::result::Result<(), AppError> {
+    if !(MIN_SPEED..=MAX_SPEED).contains(&speed) {
+        return Err(AppError::InvalidOperation(format!(
+            "Speed must be between {} and {}",
+            MIN_SPEED, MAX_SPEED
+        )));
+    }
     let sink = state.sink.lock().unwrap();
     if let Some(s) = &*sink {
-        s.set_speed(speed);
+        s.set_speed(state.tempo.set(speed, state.tempo.mode()));
         Ok(())
     } else {
         Err(AppError::InvalidOperation(
@@ -582,3 +2121,135 @@ pub fn set_speed(state: State<AudioState>, speed: f32) -> std::result::Result<()
         ))
     }
 }
+
+/// Chooses between time-stretching, which keeps the pitch, and the sink's resampling for
+/// speeds other than 1.
+#[tauri::command]
+pub fn set_speed_mode(state: State<AudioState>, mode: SpeedMode) -> std::result::Result<(), AppError> {
+    let sink = state.sink.lock().unwrap();
+    let sink_speed = state.tempo.set(state.tempo.speed(), mode);
+    if let Some(s) = &*sink {
+        s.set_speed(sink_speed);
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_speed_mode(state: State<AudioState>) -> std::result::Result<SpeedMode, AppError> {
+    Ok(state.tempo.mode())
+}
+
+#[derive(Clone, Copy, serde::Serialize, serde::Deserialize)]
+pub struct PitchShiftSettings {
+    pub semitones: i32,
+    pub cents: i32,
+}
+
+/// Transposes playback without changing its tempo.
+#[tauri::command]
+pub fn set_pitch_shift(
+    state: State<AudioState>,
+    settings: PitchShiftSettings,
+) -> std::result::Result<(), AppError> {
+    let cents = settings
+        .semitones
+        .checked_mul(100)
+        .and_then(|cents| cents.checked_add(settings.cents))
+        .map(|cents| cents as f32)
+        .filter(|cents| cents.abs() <= MAX_PITCH_CENTS);
+    let Some(cents) = cents else {
+        return Err(AppError::InvalidOperation(format!(
+            "Pitch shift must be within ±{} semitones",
+            MAX_PITCH_CENTS / 100.0
+        )));
+    };
+    state.tempo.set_pitch(cents);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_pitch_shift(state: State<AudioState>) -> std::result::Result<PitchShiftSettings, AppError> {
+    let cents = state.tempo.pitch_cents().round() as i32;
+    Ok(PitchShiftSettings {
+        semitones: cents / 100,
+        cents: cents % 100,
+    })
+}
+
+#[tauri::command]
+pub fn set_crossfade(
+    state: State<AudioState>,
+    settings: CrossfadeSettings,
+) -> std::result::Result<(), AppError> {
+    if !(0.0..=MAX_CROSSFADE.as_secs_f32()).contains(&settings.duration) {
+        return Err(AppError::InvalidOperation(format!(
+            "Crossfade must be between 0 and {} seconds",
+            MAX_CROSSFADE.as_secs()
+        )));
+    }
+    *state.crossfade.lock().unwrap() = settings;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_crossfade(state: State<AudioState>) -> std::result::Result<CrossfadeSettings, AppError> {
+    Ok(*state.crossfade.lock().unwrap())
+}
+
+/// Buffer settings take effect from the next stream that is opened.
+#[tauri::command]
+pub fn set_buffer_settings(
+    state: State<AudioState>,
+    settings: BufferSettings,
+) -> std::result::Result<(), AppError> {
+    if settings.memory_limit < MIN_BUFFER_MEMORY {
+        return Err(AppError::InvalidOperation(format!(
+            "Stream buffers need at least {} MiB",
+            MIN_BUFFER_MEMORY
+        )));
+    }
+    *state.buffering.lock().unwrap() = settings;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_buffer_settings(state: State<AudioState>) -> std::result::Result<BufferSettings, AppError> {
+    Ok(*state.buffering.lock().unwrap())
+}
+
+/// HTTP settings take effect from the next stream that is opened.
+#[tauri::command]
+pub fn set_http_settings(
+    state: State<AudioState>,
+    settings: HttpSettings,
+) -> std::result::Result<(), AppError> {
+    state.http.configure(settings)
+}
+
+#[tauri::command]
+pub fn get_http_settings(state: State<AudioState>) -> std::result::Result<HttpSettings, AppError> {
+    Ok(state.http.settings())
+}
+
+/// Normalization settings take effect from the next track that is opened.
+#[tauri::command]
+pub fn set_normalization(
+    state: State<AudioState>,
+    settings: NormalizationSettings,
+) -> std::result::Result<(), AppError> {
+    if !(-MAX_PRE_AMP..=MAX_PRE_AMP).contains(&settings.pre_amp) {
+        return Err(AppError::InvalidOperation(format!(
+            "Pre-amp must be within ±{} dB",
+            MAX_PRE_AMP
+        )));
+    }
+    *state.normalization.lock().unwrap() = settings;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_normalization(
+    state: State<AudioState>,
+) -> std::result::Result<NormalizationSettings, AppError> {
+    Ok(*state.normalization.lock().unwrap())
+}
@@ -0,0 +1,100 @@
+use std::f64::consts::PI;
+
+/// Second-order IIR filter section in transposed direct form II.
+#[derive(Clone, Copy, Debug)]
+pub struct Biquad {
+    b0: f64,
+    b1: f64,
+    b2: f64,
+    a1: f64,
+    a2: f64,
+    z1: f64,
+    z2: f64,
+}
+
+impl Biquad {
+    /// Peaking EQ from the RBJ audio EQ cookbook.
+    pub fn peaking(sample_rate: u32, frequency: f64, q: f64, gain_db: f64) -> Self {
+        let (a, cos, alpha) = cookbook_terms(sample_rate, frequency, q, gain_db);
+        Biquad::new(
+            [1.0 + alpha * a, -2.0 * cos, 1.0 - alpha * a],
+            [1.0 + alpha / a, -2.0 * cos, 1.0 - alpha / a],
+        )
+    }
+
+    /// Low shelf from the RBJ audio EQ cookbook.
+    pub fn low_shelf(sample_rate: u32, frequency: f64, q: f64, gain_db: f64) -> Self {
+        let (a, cos, alpha) = cookbook_terms(sample_rate, frequency, q, gain_db);
+        let beta = 2.0 * a.sqrt() * alpha;
+        Biquad::new(
+            [
+                a * ((a + 1.0) - (a - 1.0) * cos + beta),
+                2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+                a * ((a + 1.0) - (a - 1.0) * cos - beta),
+            ],
+            [
+                (a + 1.0) + (a - 1.0) * cos + beta,
+                -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+                (a + 1.0) + (a - 1.0) * cos - beta,
+            ],
+        )
+    }
+
+    /// High shelf from the RBJ audio EQ cookbook.
+    pub fn high_shelf(sample_rate: u32, frequency: f64, q: f64, gain_db: f64) -> Self {
+        let (a, cos, alpha) = cookbook_terms(sample_rate, frequency, q, gain_db);
+        let beta = 2.0 * a.sqrt() * alpha;
+        Biquad::new(
+            [
+                a * ((a + 1.0) + (a - 1.0) * cos + beta),
+                -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+                a * ((a + 1.0) + (a - 1.0) * cos - beta),
+            ],
+            [
+                (a + 1.0) - (a - 1.0) * cos + beta,
+                2.0 * ((a - 1.0) - (a + 1.0) * cos),
+                (a + 1.0) - (a - 1.0) * cos - beta,
+            ],
+        )
+    }
+
+    /// Builds a section from raw coefficients, normalizing them by `a[0]`.
+    pub fn new(b: [f64; 3], a: [f64; 3]) -> Self {
+        Biquad {
+            b0: b[0] / a[0],
+            b1: b[1] / a[0],
+            b2: b[2] / a[0],
+            a1: a[1] / a[0],
+            a2: a[2] / a[0],
+            z1: 0.0,
+            z2: 0.0,
+        }
+    }
+
+    /// Takes over the coefficients of `other` while keeping this section's filter state,
+    /// so retuning a running filter does not click.
+    pub fn retune(&mut self, other: &Biquad) {
+        self.b0 = other.b0;
+        self.b1 = other.b1;
+        self.b2 = other.b2;
+        self.a1 = other.a1;
+        self.a2 = other.a2;
+    }
+
+    pub fn process(&mut self, x: f64) -> f64 {
+        let y = self.b0 * x + self.z1;
+        self.z1 = self.b1 * x - self.a1 * y + self.z2;
+        self.z2 = self.b2 * x - self.a2 * y;
+        y
+    }
+}
+
+/// Returns `A`, `cos(w0)` and `alpha` as defined by the cookbook, keeping `frequency` below Nyquist.
+fn cookbook_terms(sample_rate: u32, frequency: f64, q: f64, gain_db: f64) -> (f64, f64, f64) {
+    let rate = sample_rate as f64;
+    let frequency = frequency.clamp(1.0, rate * 0.49);
+    let w0 = 2.0 * PI * frequency / rate;
+    let a = 10f64.powf(gain_db / 40.0);
+    let alpha = w0.sin() / (2.0 * q.max(0.01));
+    (a, w0.cos(), alpha)
+}
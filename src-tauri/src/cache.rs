@@ -0,0 +1,347 @@
+use crate::audio::AudioState;
+use crate::error::AppError;
+use crate::stream::StreamBuffer;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
+use std::io::{Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::State;
+
+const INDEX_FILE: &str = "index.json";
+const DEFAULT_MAX_BYTES: u64 = 1024 * 1024 * 1024;
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct CacheEntry {
+    file: String,
+    length: u64,
+    /// Downloaded `[start, end)` byte ranges, sorted and non-touching.
+    ranges: Vec<(u64, u64)>,
+    last_used: u64,
+}
+
+impl CacheEntry {
+    fn size(&self) -> u64 {
+        self.ranges.iter().map(|(start, end)| end - start).sum()
+    }
+
+    fn is_complete(&self) -> bool {
+        self.ranges == [(0, self.length)]
+    }
+
+    fn insert_range(&mut self, start: u64, end: u64) {
+        let mut merged = (start, end);
+        self.ranges.retain(|&(s, e)| {
+            if s > merged.1 || e < merged.0 {
+                return true;
+            }
+            merged = (merged.0.min(s), merged.1.max(e));
+            false
+        });
+        let index = self.ranges.partition_point(|&(s, _)| s < merged.0);
+        self.ranges.insert(index, merged);
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CacheIndex {
+    max_bytes: u64,
+    entries: HashMap<String, CacheEntry>,
+}
+
+impl Default for CacheIndex {
+    fn default() -> Self {
+        CacheIndex {
+            max_bytes: DEFAULT_MAX_BYTES,
+            entries: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct StreamCacheInfo {
+    pub entries: usize,
+    pub complete_entries: usize,
+    pub size: u64,
+    pub max_size: u64,
+}
+
+/// What the cache holds for one key, as returned by `StreamCache::lookup`.
+pub struct CachedStream {
+    path: PathBuf,
+    pub length: u64,
+    ranges: Vec<(u64, u64)>,
+}
+
+impl CachedStream {
+    pub fn is_complete(&self) -> bool {
+        self.ranges == [(0, self.length)]
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Lets `stream` read the cached bytes straight from the cache file.
+    pub fn attach(&self, stream: &StreamBuffer) -> Result<(), AppError> {
+        stream
+            .attach_cache(&self.path, &self.ranges)
+            .map_err(|e| AppError::FileOpenError(e.to_string()))
+    }
+}
+
+/// Size-bounded LRU cache of streamed tracks in the app cache directory, keyed by queue item id.
+///
+/// Partially downloaded tracks keep the byte ranges they have, so a later play only fetches
+/// what is missing.
+#[derive(Default)]
+pub struct StreamCache {
+    dir: Option<PathBuf>,
+    index: CacheIndex,
+    /// Keys with downloads writing into them and how many, never evicted. The same track
+    /// can be playing and preloading at once.
+    pinned: HashMap<String, usize>,
+}
+
+impl StreamCache {
+    pub fn load(&mut self, dir: &Path) {
+        if let Ok(data) = fs::read(dir.join(INDEX_FILE)) {
+            self.index = serde_json::from_slice(&data).unwrap_or_default();
+        }
+        // Drop entries whose file went missing
+        self.index
+            .entries
+            .retain(|_, entry| dir.join(&entry.file).exists());
+        self.dir = Some(dir.to_path_buf());
+    }
+
+    /// Returns the cached bytes for `key` and marks it as recently used.
+    pub fn lookup(&mut self, key: &str) -> Option<CachedStream> {
+        let dir = self.dir.clone()?;
+        let entry = self.index.entries.get_mut(key)?;
+        entry.last_used = now();
+        Some(CachedStream {
+            path: dir.join(&entry.file),
+            length: entry.length,
+            ranges: entry.ranges.clone(),
+        })
+    }
+
+    pub fn remove(&mut self, key: &str) {
+        self.remove_entry(key);
+        self.save();
+    }
+
+    fn remove_entry(&mut self, key: &str) {
+        if let (Some(dir), Some(entry)) = (&self.dir, self.index.entries.remove(key)) {
+            let _ = fs::remove_file(dir.join(entry.file));
+        }
+    }
+
+    pub fn info(&self) -> StreamCacheInfo {
+        let entries = self.index.entries.values();
+        StreamCacheInfo {
+            entries: self.index.entries.len(),
+            complete_entries: entries.clone().filter(|e| e.is_complete()).count(),
+            size: entries.map(CacheEntry::size).sum(),
+            max_size: self.index.max_bytes,
+        }
+    }
+
+    pub fn set_max_bytes(&mut self, max_bytes: u64) {
+        self.index.max_bytes = max_bytes;
+        self.evict();
+        self.save();
+    }
+
+    /// Removes every entry that is not being downloaded right now.
+    pub fn clear(&mut self) {
+        let keys: Vec<String> = self
+            .index
+            .entries
+            .keys()
+            .filter(|key| !self.pinned.contains_key(*key))
+            .cloned()
+            .collect();
+        for key in keys {
+            self.remove_entry(&key);
+        }
+        self.save();
+    }
+
+    fn entry(&mut self, key: &str, length: u64) -> &mut CacheEntry {
+        // The file changed on the server, start over
+        if self
+            .index
+            .entries
+            .get(key)
+            .is_some_and(|entry| entry.length != length)
+        {
+            self.remove_entry(key);
+        }
+        if self.index.entries.contains_key(key) {
+            return self.index.entries.get_mut(key).unwrap();
+        }
+
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        let mut hash = hasher.finish();
+        let taken: HashSet<&str> = self
+            .index
+            .entries
+            .values()
+            .map(|entry| entry.file.as_str())
+            .collect();
+        while taken.contains(format!("{:016x}", hash).as_str()) {
+            hash = hash.wrapping_add(1);
+        }
+        let file = format!("{:016x}", hash);
+
+        self.index
+            .entries
+            .entry(key.to_string())
+            .or_insert(CacheEntry {
+                file,
+                length,
+                ranges: Vec::new(),
+                last_used: now(),
+            })
+    }
+
+    /// Drops least recently used entries until the cache fits its size limit.
+    fn evict(&mut self) {
+        let mut size: u64 = self.index.entries.values().map(CacheEntry::size).sum();
+        let mut candidates: Vec<(u64, String)> = self
+            .index
+            .entries
+            .iter()
+            .filter(|(key, _)| !self.pinned.contains_key(*key))
+            .map(|(key, entry)| (entry.last_used, key.clone()))
+            .collect();
+        candidates.sort();
+
+        for (_, key) in candidates {
+            if size <= self.index.max_bytes {
+                break;
+            }
+            if let Some(entry) = self.index.entries.get(&key) {
+                size -= entry.size();
+            }
+            self.remove_entry(&key);
+        }
+    }
+
+    fn save(&self) {
+        let Some(dir) = &self.dir else {
+            return;
+        };
+        let _ = fs::create_dir_all(dir);
+        if let Ok(data) = serde_json::to_vec(&self.index) {
+            let _ = fs::write(dir.join(INDEX_FILE), data);
+        }
+    }
+}
+
+/// Writes downloaded bytes of one stream into its cache file.
+pub struct CacheWriter {
+    cache: Arc<Mutex<StreamCache>>,
+    key: String,
+    file: File,
+}
+
+impl CacheWriter {
+    /// Opens the cache file for `key`, starting a new entry if there is none for this `length`.
+    pub fn open(cache: &Arc<Mutex<StreamCache>>, key: &str, length: u64) -> Option<Self> {
+        let mut guard = cache.lock().unwrap();
+        let dir = guard.dir.clone()?;
+        // Another download of a different version of the file owns the entry
+        let conflicting = guard
+            .index
+            .entries
+            .get(key)
+            .is_some_and(|entry| entry.length != length);
+        if conflicting && guard.pinned.contains_key(key) {
+            return None;
+        }
+        fs::create_dir_all(&dir).ok()?;
+        let path = dir.join(&guard.entry(key, length).file);
+        let file = File::options()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(path)
+            .ok()?;
+        *guard.pinned.entry(key.to_string()).or_default() += 1;
+
+        Some(CacheWriter {
+            cache: cache.clone(),
+            key: key.to_string(),
+            file,
+        })
+    }
+
+    pub fn write(&mut self, offset: u64, bytes: &[u8]) {
+        let written = self
+            .file
+            .seek(SeekFrom::Start(offset))
+            .and_then(|_| self.file.write_all(bytes));
+        if written.is_ok() {
+            let mut cache = self.cache.lock().unwrap();
+            if let Some(entry) = cache.index.entries.get_mut(&self.key) {
+                entry.insert_range(offset, offset + bytes.len() as u64);
+            }
+        }
+    }
+
+    /// Persists the index so the ranges written so far survive a restart.
+    pub fn save(&self) {
+        let mut cache = self.cache.lock().unwrap();
+        cache.evict();
+        cache.save();
+    }
+}
+
+impl Drop for CacheWriter {
+    fn drop(&mut self) {
+        let mut cache = self.cache.lock().unwrap();
+        if let Some(count) = cache.pinned.get_mut(&self.key) {
+            *count -= 1;
+            if *count == 0 {
+                cache.pinned.remove(&self.key);
+            }
+        }
+        cache.evict();
+        cache.save();
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[tauri::command]
+pub fn get_stream_cache(state: State<AudioState>) -> Result<StreamCacheInfo, AppError> {
+    Ok(state.cache.lock().unwrap().info())
+}
+
+#[tauri::command]
+pub fn clear_stream_cache(state: State<AudioState>) -> Result<(), AppError> {
+    state.cache.lock().unwrap().clear();
+    Ok(())
+}
+
+#[tauri::command]
+pub fn set_stream_cache_limit(state: State<AudioState>, max_bytes: u64) -> Result<(), AppError> {
+    state.cache.lock().unwrap().set_max_bytes(max_bytes);
+    Ok(())
+}
@@ -0,0 +1,279 @@
+use crate::error::AppError;
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Crossfeed ranges accepted by bs2b.
+const MIN_CROSSFEED_CUTOFF: f32 = 300.0;
+const MAX_CROSSFEED_CUTOFF: f32 = 2000.0;
+const MIN_CROSSFEED_FEED: f32 = 1.0;
+const MAX_CROSSFEED_FEED: f32 = 15.0;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CrossfeedSettings {
+    pub enabled: bool,
+    /// Cut-off frequency in Hz of the signal fed to the opposite ear.
+    pub cutoff: f32,
+    /// Level in dB by which the crossfed signal sits below the direct one at low frequencies.
+    pub feed: f32,
+}
+
+impl Default for CrossfeedSettings {
+    /// bs2b's default level, 700 Hz and 4.5 dB.
+    fn default() -> Self {
+        CrossfeedSettings {
+            enabled: false,
+            cutoff: 700.0,
+            feed: 4.5,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ChannelSettings {
+    /// From `-1.0`, left only, to `1.0`, right only.
+    pub balance: f32,
+    /// Plays the average of all channels on every channel.
+    pub mono: bool,
+    pub swap: bool,
+    #[serde(default)]
+    pub crossfeed: CrossfeedSettings,
+}
+
+impl ChannelSettings {
+    pub fn validate(&self) -> Result<(), AppError> {
+        if !(-1.0..=1.0).contains(&self.balance) {
+            return Err(AppError::InvalidOperation(
+                "Balance must be between -1 and 1".to_string(),
+            ));
+        }
+        let crossfeed = &self.crossfeed;
+        if !(MIN_CROSSFEED_CUTOFF..=MAX_CROSSFEED_CUTOFF).contains(&crossfeed.cutoff)
+            || !(MIN_CROSSFEED_FEED..=MAX_CROSSFEED_FEED).contains(&crossfeed.feed)
+        {
+            return Err(AppError::InvalidOperation(format!(
+                "Crossfeed needs a cut-off between {} and {} Hz and a feed between {} and {} dB",
+                MIN_CROSSFEED_CUTOFF, MAX_CROSSFEED_CUTOFF, MIN_CROSSFEED_FEED, MAX_CROSSFEED_FEED
+            )));
+        }
+        Ok(())
+    }
+
+    fn is_neutral(&self) -> bool {
+        self.balance == 0.0 && !self.mono && !self.swap && !self.crossfeed.enabled
+    }
+}
+
+/// Channel settings shared with every playing `ChannelMixer`, which pick changes up on
+/// their next frame.
+#[derive(Default)]
+pub struct ChannelControl {
+    settings: Mutex<ChannelSettings>,
+    version: AtomicU64,
+}
+
+impl ChannelControl {
+    pub fn settings(&self) -> ChannelSettings {
+        *self.settings.lock().unwrap()
+    }
+
+    pub fn set(&self, settings: ChannelSettings) {
+        *self.settings.lock().unwrap() = settings;
+        self.version.fetch_add(1, Ordering::Release);
+    }
+}
+
+/// Bauer stereophonic-to-binaural crossfeed as done by bs2b: each ear gets the other
+/// channel low-passed, on top of its own channel with a matching high boost.
+struct Crossfeed {
+    a0_lo: f64,
+    b1_lo: f64,
+    a0_hi: f64,
+    a1_hi: f64,
+    b1_hi: f64,
+    gain: f64,
+    lo: [f64; 2],
+    hi: [f64; 2],
+    previous: [f64; 2],
+}
+
+impl Crossfeed {
+    fn new(settings: &CrossfeedSettings, sample_rate: u32) -> Self {
+        let rate = sample_rate.max(1) as f64;
+        let feed = settings.feed as f64;
+        let gain_lo_db = feed * -5.0 / 6.0 - 3.0;
+        let gain_hi_db = feed / 6.0 - 3.0;
+        let g_lo = 10f64.powf(gain_lo_db / 20.0);
+        let g_hi = 1.0 - 10f64.powf(gain_hi_db / 20.0);
+        let cutoff_lo = settings.cutoff as f64;
+        let cutoff_hi = cutoff_lo * 2f64.powf((gain_lo_db - 20.0 * g_hi.log10()) / 12.0);
+
+        let x_lo = (-2.0 * PI * cutoff_lo / rate).exp();
+        let x_hi = (-2.0 * PI * cutoff_hi / rate).exp();
+        Crossfeed {
+            a0_lo: g_lo * (1.0 - x_lo),
+            b1_lo: x_lo,
+            a0_hi: 1.0 - g_hi * (1.0 - x_hi),
+            a1_hi: -x_hi,
+            b1_hi: x_hi,
+            gain: 1.0 / (1.0 - g_hi + g_lo),
+            lo: [0.0; 2],
+            hi: [0.0; 2],
+            previous: [0.0; 2],
+        }
+    }
+
+    fn process(&mut self, left: f32, right: f32) -> (f32, f32) {
+        let input = [left as f64, right as f64];
+        for (c, &x) in input.iter().enumerate() {
+            self.lo[c] = self.a0_lo * x + self.b1_lo * self.lo[c];
+            self.hi[c] = self.a0_hi * x + self.a1_hi * self.previous[c] + self.b1_hi * self.hi[c];
+        }
+        self.previous = input;
+        (
+            ((self.hi[0] + self.lo[1]) * self.gain) as f32,
+            ((self.hi[1] + self.lo[0]) * self.gain) as f32,
+        )
+    }
+}
+
+/// Balance, mono downmix, left/right swap and headphone crossfeed, one frame at a time.
+/// Everything but the downmix only applies to stereo.
+pub struct ChannelMixer<S> {
+    inner: S,
+    control: Arc<ChannelControl>,
+    version: Option<u64>,
+    settings: ChannelSettings,
+    sample_rate: u32,
+    channels: u16,
+    crossfeed: Option<Crossfeed>,
+    frame: Vec<f32>,
+    cursor: usize,
+}
+
+impl<S> ChannelMixer<S>
+where
+    S: Source<Item = f32>,
+{
+    pub fn new(inner: S, control: Arc<ChannelControl>) -> Self {
+        ChannelMixer {
+            sample_rate: inner.sample_rate(),
+            channels: inner.channels(),
+            inner,
+            control,
+            version: None,
+            settings: ChannelSettings::default(),
+            crossfeed: None,
+            frame: Vec::new(),
+            cursor: 0,
+        }
+    }
+
+    /// Picks up new settings, redesigning the crossfeed when it or the stream format changed.
+    fn refresh(&mut self) {
+        let version = self.control.version.load(Ordering::Acquire);
+        let sample_rate = self.inner.sample_rate();
+        let channels = self.inner.channels();
+        let format_changed = sample_rate != self.sample_rate || channels != self.channels;
+        if self.version == Some(version) && !format_changed {
+            return;
+        }
+
+        let settings = self.control.settings();
+        let crossfeed = settings.crossfeed;
+        if format_changed || crossfeed != self.settings.crossfeed || self.crossfeed.is_none() {
+            self.crossfeed = (crossfeed.enabled && channels == 2)
+                .then(|| Crossfeed::new(&crossfeed, sample_rate));
+        }
+        self.version = Some(version);
+        self.settings = settings;
+        self.sample_rate = sample_rate;
+        self.channels = channels;
+    }
+
+    fn process(&mut self) {
+        let settings = self.settings;
+        if settings.mono && self.frame.len() > 1 {
+            let average = self.frame.iter().sum::<f32>() / self.frame.len() as f32;
+            self.frame.iter_mut().for_each(|s| *s = average);
+        }
+        let [left, right] = &mut self.frame[..] else {
+            return;
+        };
+
+        if settings.swap {
+            std::mem::swap(left, right);
+        }
+        if let (Some(crossfeed), false) = (&mut self.crossfeed, settings.mono) {
+            (*left, *right) = crossfeed.process(*left, *right);
+        }
+        *left *= (1.0 - settings.balance).min(1.0);
+        *right *= (1.0 + settings.balance).min(1.0);
+    }
+}
+
+impl<S> Iterator for ChannelMixer<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.cursor == self.frame.len() {
+            self.refresh();
+            self.frame.clear();
+            self.cursor = 0;
+            for _ in 0..self.channels.max(1) {
+                match self.inner.next() {
+                    Some(sample) => self.frame.push(sample),
+                    None => break,
+                }
+            }
+            if !self.settings.is_neutral() {
+                self.process();
+            }
+        }
+
+        let sample = *self.frame.get(self.cursor)?;
+        self.cursor += 1;
+        Some(sample)
+    }
+}
+
+impl<S> Source for ChannelMixer<S>
+where
+    S: Source<Item = f32>,
+{
+    fn current_span_len(&self) -> Option<usize> {
+        // The rest of the frame has already been read from the inner source
+        let buffered = self.frame.len() - self.cursor;
+        self.inner.current_span_len().map(|len| len + buffered)
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), rodio::source::SeekError> {
+        self.inner.try_seek(pos)?;
+        self.frame.clear();
+        self.cursor = 0;
+        if let Some(crossfeed) = &mut self.crossfeed {
+            *crossfeed = Crossfeed::new(&self.settings.crossfeed, self.sample_rate);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,289 @@
+use crate::audio::AudioState;
+use crate::biquad::Biquad;
+use crate::error::AppError;
+use rodio::{Sample, Source};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tauri::State;
+
+const MAX_BANDS: usize = 31;
+const MAX_GAIN_DB: f32 = 24.0;
+/// Centre frequencies of the built-in ten band graphic presets.
+const GRAPHIC_FREQUENCIES: [f32; 10] = [
+    31.0, 62.0, 125.0, 250.0, 500.0, 1000.0, 2000.0, 4000.0, 8000.0, 16000.0,
+];
+const GRAPHIC_Q: f32 = 1.41;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum FilterKind {
+    Peaking,
+    LowShelf,
+    HighShelf,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct EqBand {
+    pub kind: FilterKind,
+    /// Centre or corner frequency in Hz.
+    pub frequency: f32,
+    /// Gain in dB.
+    pub gain: f32,
+    pub q: f32,
+}
+
+impl EqBand {
+    fn filter(&self, sample_rate: u32) -> Biquad {
+        let (frequency, q, gain) = (self.frequency as f64, self.q as f64, self.gain as f64);
+        match self.kind {
+            FilterKind::Peaking => Biquad::peaking(sample_rate, frequency, q, gain),
+            FilterKind::LowShelf => Biquad::low_shelf(sample_rate, frequency, q, gain),
+            FilterKind::HighShelf => Biquad::high_shelf(sample_rate, frequency, q, gain),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct EqualizerSettings {
+    pub enabled: bool,
+    /// Gain in dB applied before the bands, usually negative to leave headroom for boosts.
+    pub pre_amp: f32,
+    pub bands: Vec<EqBand>,
+}
+
+impl EqualizerSettings {
+    fn validate(&self) -> Result<(), AppError> {
+        if self.bands.len() > MAX_BANDS {
+            return Err(AppError::InvalidOperation(format!(
+                "Equalizer supports at most {} bands",
+                MAX_BANDS
+            )));
+        }
+        let gain_in_range = |gain: f32| (-MAX_GAIN_DB..=MAX_GAIN_DB).contains(&gain);
+        if !gain_in_range(self.pre_amp) {
+            return Err(AppError::InvalidOperation(format!(
+                "Pre-amp must be within ±{} dB",
+                MAX_GAIN_DB
+            )));
+        }
+        for band in &self.bands {
+            if !(band.frequency > 0.0 && band.q > 0.0 && gain_in_range(band.gain)) {
+                return Err(AppError::InvalidOperation(format!(
+                    "Invalid equalizer band at {} Hz",
+                    band.frequency
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Clone)]
+pub struct EqualizerPreset {
+    pub name: String,
+    pub settings: EqualizerSettings,
+}
+
+fn graphic_preset(name: &str, gains: [f32; 10]) -> EqualizerPreset {
+    let peak = gains.iter().cloned().fold(0.0, f32::max);
+    EqualizerPreset {
+        name: name.to_string(),
+        settings: EqualizerSettings {
+            enabled: true,
+            pre_amp: -peak,
+            bands: GRAPHIC_FREQUENCIES
+                .iter()
+                .zip(gains)
+                .map(|(&frequency, gain)| EqBand {
+                    kind: FilterKind::Peaking,
+                    frequency,
+                    gain,
+                    q: GRAPHIC_Q,
+                })
+                .collect(),
+        },
+    }
+}
+
+pub fn presets() -> Vec<EqualizerPreset> {
+    vec![
+        graphic_preset("Flat", [0.0; 10]),
+        graphic_preset("Bass Boost", [6.0, 5.0, 4.0, 2.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0]),
+        graphic_preset("Treble Boost", [0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 2.0, 4.0, 5.0, 6.0]),
+        graphic_preset("Vocal", [-2.0, -2.0, -1.0, 1.0, 3.0, 3.0, 2.0, 1.0, 0.0, -1.0]),
+        graphic_preset("Rock", [5.0, 4.0, 2.0, -1.0, -2.0, -1.0, 2.0, 3.0, 4.0, 4.0]),
+        graphic_preset("Classical", [4.0, 3.0, 2.0, 1.0, -1.0, -1.0, 0.0, 2.0, 3.0, 4.0]),
+        graphic_preset("Loudness", [6.0, 4.0, 0.0, 0.0, -2.0, 0.0, -1.0, -4.0, 4.0, 2.0]),
+    ]
+}
+
+/// Equalizer settings shared with every playing `Equalizer`, which pick changes up on
+/// their next frame.
+#[derive(Default)]
+pub struct EqualizerControl {
+    settings: Mutex<EqualizerSettings>,
+    version: AtomicU64,
+}
+
+impl EqualizerControl {
+    pub fn settings(&self) -> EqualizerSettings {
+        self.settings.lock().unwrap().clone()
+    }
+
+    pub fn set(&self, settings: EqualizerSettings) {
+        *self.settings.lock().unwrap() = settings;
+        self.version.fetch_add(1, Ordering::Release);
+    }
+}
+
+/// Multi-band biquad equalizer stage, which also converts the decoder output to `f32`.
+pub struct Equalizer<S> {
+    inner: S,
+    control: Arc<EqualizerControl>,
+    version: Option<u64>,
+    sample_rate: u32,
+    channels: u16,
+    enabled: bool,
+    pre_amp: f64,
+    /// One filter chain per channel.
+    filters: Vec<Vec<Biquad>>,
+    channel: usize,
+}
+
+impl<S> Equalizer<S>
+where
+    S: Source,
+    S::Item: Sample,
+{
+    pub fn new(inner: S, control: Arc<EqualizerControl>) -> Self {
+        Equalizer {
+            sample_rate: inner.sample_rate(),
+            channels: inner.channels(),
+            inner,
+            control,
+            version: None,
+            enabled: false,
+            pre_amp: 1.0,
+            filters: Vec::new(),
+            channel: 0,
+        }
+    }
+
+    /// Rebuilds the filters when the settings or the stream format changed.
+    fn refresh(&mut self) {
+        let version = self.control.version.load(Ordering::Acquire);
+        let sample_rate = self.inner.sample_rate();
+        let channels = self.inner.channels();
+        let format_changed = sample_rate != self.sample_rate || channels != self.channels;
+        if self.version == Some(version) && !format_changed {
+            return;
+        }
+
+        let settings = self.control.settings();
+        let designed: Vec<Biquad> = settings.bands.iter().map(|b| b.filter(sample_rate)).collect();
+        let reusable = !format_changed
+            && self.filters.len() == channels as usize
+            && self.filters.iter().all(|chain| chain.len() == designed.len());
+
+        if reusable {
+            for chain in &mut self.filters {
+                for (filter, design) in chain.iter_mut().zip(&designed) {
+                    filter.retune(design);
+                }
+            }
+        } else {
+            self.filters = vec![designed; channels.max(1) as usize];
+        }
+
+        self.version = Some(version);
+        self.sample_rate = sample_rate;
+        self.channels = channels;
+        self.enabled = settings.enabled;
+        self.pre_amp = 10f64.powf(settings.pre_amp as f64 / 20.0);
+        self.channel = 0;
+    }
+}
+
+impl<S> Iterator for Equalizer<S>
+where
+    S: Source,
+    S::Item: Sample,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.channel == 0 {
+            self.refresh();
+        }
+
+        let sample = self.inner.next()?.to_f32();
+        let channel = self.channel;
+        self.channel = (self.channel + 1) % self.filters.len();
+
+        if !self.enabled {
+            return Some(sample);
+        }
+        let mut x = sample as f64 * self.pre_amp;
+        for filter in &mut self.filters[channel] {
+            x = filter.process(x);
+        }
+        Some(x as f32)
+    }
+}
+
+impl<S> Source for Equalizer<S>
+where
+    S: Source,
+    S::Item: Sample,
+{
+    fn current_span_len(&self) -> Option<usize> {
+        self.inner.current_span_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), rodio::source::SeekError> {
+        self.inner.try_seek(pos)
+    }
+}
+
+#[tauri::command]
+pub fn get_equalizer(state: State<AudioState>) -> Result<EqualizerSettings, AppError> {
+    Ok(state.equalizer.settings())
+}
+
+#[tauri::command]
+pub fn set_equalizer(state: State<AudioState>, settings: EqualizerSettings) -> Result<(), AppError> {
+    settings.validate()?;
+    state.equalizer.set(settings);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_equalizer_presets() -> Result<Vec<EqualizerPreset>, AppError> {
+    Ok(presets())
+}
+
+#[tauri::command]
+pub fn apply_equalizer_preset(state: State<AudioState>, name: String) -> Result<(), AppError> {
+    let preset = presets()
+        .into_iter()
+        .find(|p| p.name == name)
+        .ok_or_else(|| AppError::InvalidOperation(format!("Unknown equalizer preset: {}", name)))?;
+    state.equalizer.set(preset.settings);
+    Ok(())
+}
@@ -12,6 +12,7 @@ pub enum AppError {
     SeekError(String),
     NetworkError(String),
     MediaControlsError(String),
+    OutputDeviceError(String),
     TauriError(String),  // Add this variant
 }
 
@@ -27,6 +28,7 @@ impl Display for AppError {
             AppError::SeekError(err) => write!(f, "Failed to seek: {}", err),
             AppError::NetworkError(err) => write!(f, "Network error: {}", err),
             AppError::MediaControlsError(err) => write!(f, "Media controls error: {}", err),
+            AppError::OutputDeviceError(err) => write!(f, "Output device error: {}", err),
             AppError::TauriError(err) => write!(f, "Tauri error: {}", err),
         }
     }
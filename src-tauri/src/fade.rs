@@ -0,0 +1,375 @@
+use rodio::{Sample, Source};
+use serde::{Deserialize, Serialize};
+use std::f32::consts::FRAC_PI_2;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum FadeCurve {
+    #[default]
+    Linear,
+    EqualPower,
+}
+
+impl FadeCurve {
+    /// Maps linear progress `t` in `0..=1` onto the curve, for a ramp going up or down.
+    fn shape(self, t: f32, rising: bool) -> f32 {
+        match self {
+            FadeCurve::Linear => t,
+            FadeCurve::EqualPower if rising => (t * FRAC_PI_2).sin(),
+            FadeCurve::EqualPower => 1.0 - (t * FRAC_PI_2).cos(),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct FadeRequest {
+    from: Option<f32>,
+    to: f32,
+    duration: Duration,
+    curve: FadeCurve,
+    stop_at_end: bool,
+}
+
+/// Shared handle used to ramp the gain of a `Fader` from outside the audio thread.
+#[derive(Default)]
+pub struct FadeControl {
+    request: Mutex<Option<FadeRequest>>,
+    has_request: AtomicBool,
+}
+
+impl FadeControl {
+    fn submit(&self, request: FadeRequest) {
+        *self.request.lock().unwrap() = Some(request);
+        self.has_request.store(true, Ordering::Release);
+    }
+
+    /// Ramps from silence up to unity gain.
+    pub fn fade_in(&self, duration: Duration, curve: FadeCurve) {
+        self.submit(FadeRequest {
+            from: Some(0.0),
+            to: 1.0,
+            duration,
+            curve,
+            stop_at_end: false,
+        });
+    }
+
+    /// Ramps down to silence and ends the source once the ramp is complete.
+    pub fn fade_out(&self, duration: Duration, curve: FadeCurve) {
+        self.submit(FadeRequest {
+            from: None,
+            to: 0.0,
+            duration,
+            curve,
+            stop_at_end: true,
+        });
+    }
+
+    fn take(&self) -> Option<FadeRequest> {
+        if self.has_request.swap(false, Ordering::Acquire) {
+            self.request.lock().unwrap().take()
+        } else {
+            None
+        }
+    }
+}
+
+struct Ramp {
+    from: f32,
+    to: f32,
+    curve: FadeCurve,
+    total_frames: u64,
+    elapsed_frames: u64,
+    stop_at_end: bool,
+}
+
+/// Applies gain ramps requested through a `FadeControl`, one gain value per frame.
+pub struct Fader<S> {
+    inner: S,
+    control: Arc<FadeControl>,
+    gain: f32,
+    ramp: Option<Ramp>,
+    frame_offset: u16,
+    stopped: bool,
+}
+
+impl<S> Fader<S>
+where
+    S: Source,
+    S::Item: Sample,
+{
+    pub fn new(inner: S, control: Arc<FadeControl>) -> Self {
+        Fader {
+            inner,
+            control,
+            gain: 1.0,
+            ramp: None,
+            frame_offset: 0,
+            stopped: false,
+        }
+    }
+
+    fn start_ramp(&mut self, request: FadeRequest) {
+        let from = request.from.unwrap_or(self.gain);
+        let total_frames =
+            (request.duration.as_secs_f64() * self.inner.sample_rate() as f64) as u64;
+        self.gain = from;
+        self.ramp = Some(Ramp {
+            from,
+            to: request.to,
+            curve: request.curve,
+            total_frames: total_frames.max(1),
+            elapsed_frames: 0,
+            stop_at_end: request.stop_at_end,
+        });
+    }
+
+    fn advance_gain(&mut self) {
+        if let Some(request) = self.control.take() {
+            self.start_ramp(request);
+        }
+
+        if let Some(ramp) = &mut self.ramp {
+            ramp.elapsed_frames += 1;
+            if ramp.elapsed_frames >= ramp.total_frames {
+                self.gain = ramp.to;
+                self.stopped = ramp.stop_at_end;
+                self.ramp = None;
+            } else {
+                let t = ramp.elapsed_frames as f32 / ramp.total_frames as f32;
+                let shaped = ramp.curve.shape(t, ramp.to > ramp.from);
+                self.gain = ramp.from + (ramp.to - ramp.from) * shaped;
+            }
+        }
+    }
+}
+
+impl<S> Iterator for Fader<S>
+where
+    S: Source,
+    S::Item: Sample,
+{
+    type Item = S::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.frame_offset == 0 {
+            self.advance_gain();
+        }
+        if self.stopped {
+            return None;
+        }
+
+        let sample = self.inner.next()?;
+        self.frame_offset = (self.frame_offset + 1) % self.inner.channels().max(1);
+
+        if self.gain == 1.0 {
+            Some(sample)
+        } else {
+            Some(sample.amplify(self.gain))
+        }
+    }
+}
+
+impl<S> Source for Fader<S>
+where
+    S: Source,
+    S::Item: Sample,
+{
+    fn current_span_len(&self) -> Option<usize> {
+        self.inner.current_span_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), rodio::source::SeekError> {
+        self.inner.try_seek(pos)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TransportFadeSettings {
+    /// Seconds of the ramp around pause, resume and stop.
+    pub pause: f32,
+    /// Seconds of the ramp out of and back into a seek.
+    pub seek: f32,
+}
+
+impl Default for TransportFadeSettings {
+    fn default() -> Self {
+        TransportFadeSettings {
+            pause: 0.12,
+            seek: 0.06,
+        }
+    }
+}
+
+/// Gain every track follows around pause, resume, stop and seek, so the sink is only
+/// toggled once its output has gone quiet.
+pub struct TransportFade {
+    settings: Mutex<TransportFadeSettings>,
+    /// Gain the `TransportFader` stages move towards, as f32 bits.
+    target: AtomicU32,
+    /// Length of a full ramp between silence and unity gain, in nanoseconds.
+    ramp: AtomicU64,
+    /// Bumped on every ramp so a delayed pause or stop can tell it has been overtaken.
+    generation: AtomicU64,
+}
+
+impl Default for TransportFade {
+    fn default() -> Self {
+        TransportFade {
+            settings: Mutex::new(TransportFadeSettings::default()),
+            target: AtomicU32::new(1f32.to_bits()),
+            ramp: AtomicU64::new(0),
+            generation: AtomicU64::new(0),
+        }
+    }
+}
+
+impl TransportFade {
+    pub fn settings(&self) -> TransportFadeSettings {
+        *self.settings.lock().unwrap()
+    }
+
+    pub fn set_settings(&self, settings: TransportFadeSettings) {
+        *self.settings.lock().unwrap() = settings;
+    }
+
+    pub fn pause_duration(&self) -> Duration {
+        Duration::from_secs_f32(self.settings().pause.max(0.0))
+    }
+
+    pub fn seek_duration(&self) -> Duration {
+        Duration::from_secs_f32(self.settings().seek.max(0.0))
+    }
+
+    /// Ramps every track to silence, returning the generation to check with `is_current`
+    /// before acting on the sink once it is done.
+    pub fn fade_out(&self, duration: Duration) -> u64 {
+        self.ramp_to(0.0, duration)
+    }
+
+    pub fn fade_in(&self, duration: Duration) -> u64 {
+        self.ramp_to(1.0, duration)
+    }
+
+    pub fn is_current(&self, generation: u64) -> bool {
+        self.generation.load(Ordering::Acquire) == generation
+    }
+
+    fn ramp_to(&self, target: f32, duration: Duration) -> u64 {
+        self.ramp
+            .store(duration.as_nanos() as u64, Ordering::Release);
+        self.target.store(target.to_bits(), Ordering::Release);
+        self.generation.fetch_add(1, Ordering::AcqRel) + 1
+    }
+
+    fn target(&self) -> f32 {
+        f32::from_bits(self.target.load(Ordering::Acquire))
+    }
+
+    /// Gain change per frame at `sample_rate`, `None` to jump straight to the target.
+    fn step(&self, sample_rate: u32) -> Option<f32> {
+        let ramp = self.ramp.load(Ordering::Acquire) as f64 / 1e9;
+        let frames = ramp * sample_rate as f64;
+        (frames >= 1.0).then(|| (1.0 / frames) as f32)
+    }
+}
+
+/// Moves its gain linearly towards the `TransportFade` target, one step per frame.
+pub struct TransportFader<S> {
+    inner: S,
+    control: Arc<TransportFade>,
+    gain: f32,
+    frame_offset: u16,
+}
+
+impl<S> TransportFader<S>
+where
+    S: Source,
+    S::Item: Sample,
+{
+    pub fn new(inner: S, control: Arc<TransportFade>) -> Self {
+        TransportFader {
+            gain: control.target(),
+            inner,
+            control,
+            frame_offset: 0,
+        }
+    }
+
+    fn advance_gain(&mut self) {
+        let target = self.control.target();
+        if self.gain == target {
+            return;
+        }
+        self.gain = match self.control.step(self.inner.sample_rate()) {
+            Some(step) if self.gain < target => (self.gain + step).min(target),
+            Some(step) => (self.gain - step).max(target),
+            None => target,
+        };
+    }
+}
+
+impl<S> Iterator for TransportFader<S>
+where
+    S: Source,
+    S::Item: Sample,
+{
+    type Item = S::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.frame_offset == 0 {
+            self.advance_gain();
+        }
+
+        let sample = self.inner.next()?;
+        self.frame_offset = (self.frame_offset + 1) % self.inner.channels().max(1);
+
+        if self.gain == 1.0 {
+            Some(sample)
+        } else {
+            Some(sample.amplify(self.gain))
+        }
+    }
+}
+
+impl<S> Source for TransportFader<S>
+where
+    S: Source,
+    S::Item: Sample,
+{
+    fn current_span_len(&self) -> Option<usize> {
+        self.inner.current_span_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), rodio::source::SeekError> {
+        self.inner.try_seek(pos)
+    }
+}
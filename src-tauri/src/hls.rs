@@ -0,0 +1,917 @@
+use crate::error::AppError;
+use crate::stream::{Overflow, StreamBuffer, StreamDecoder};
+use cbc::cipher::{block_padding::Pkcs7, BlockDecryptMut, KeyIvInit};
+use crate::http::Requester;
+use reqwest::Url;
+use rodio::Source;
+use std::collections::HashMap;
+use std::io;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+
+type Aes128CbcDec = cbc::Decryptor<aes::Aes128>;
+
+/// Content types HLS playlists are served with.
+const PLAYLIST_TYPES: [&str; 3] = [
+    "application/vnd.apple.mpegurl",
+    "application/x-mpegurl",
+    "audio/mpegurl",
+];
+/// Segments behind the end of a live playlist that playback starts at.
+const LIVE_EDGE_SEGMENTS: usize = 3;
+const SEGMENT_ATTEMPTS: u32 = 3;
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+const TS_PACKET: usize = 188;
+/// Samples decoded and dropped per call while skipping to a seek target, so the audio
+/// callback is not held up for a whole segment.
+const SKIP_PER_CALL: usize = 4096;
+
+/// Whether a response is an HLS playlist, by its content type or else the URL's extension.
+pub fn is_playlist(url: &Url, content_type: Option<&str>) -> bool {
+    let mime = content_type
+        .and_then(|t| t.split(';').next())
+        .map(|t| t.trim().to_ascii_lowercase());
+    match mime {
+        Some(mime) if PLAYLIST_TYPES.contains(&mime.as_str()) => true,
+        _ => url.path().to_ascii_lowercase().ends_with(".m3u8"),
+    }
+}
+
+#[derive(Clone, PartialEq)]
+struct SegmentKey {
+    uri: Url,
+    iv: Option<[u8; 16]>,
+}
+
+#[derive(Clone)]
+struct Segment {
+    sequence: u64,
+    url: Url,
+    /// Seconds, from `#EXTINF`.
+    duration: f64,
+    /// Length and offset of a `#EXT-X-BYTERANGE` sub-range.
+    range: Option<(u64, u64)>,
+    key: Option<SegmentKey>,
+    /// Initialisation section of fragmented MP4 segments.
+    map: Option<Url>,
+}
+
+impl Segment {
+    /// IV the segment is decrypted with. Without an explicit one the sequence number is used,
+    /// big endian.
+    fn iv(&self) -> Option<[u8; 16]> {
+        let key = self.key.as_ref()?;
+        Some(key.iv.unwrap_or((self.sequence as u128).to_be_bytes()))
+    }
+}
+
+struct MediaPlaylist {
+    target_duration: f64,
+    segments: Vec<Segment>,
+    /// Set by `#EXT-X-ENDLIST`, live playlists grow until then.
+    ended: bool,
+}
+
+enum Playlist {
+    /// A multivariant playlist, reduced to the media playlist chosen from it.
+    Multivariant(Url),
+    Media(MediaPlaylist),
+}
+
+/// Splits an attribute list such as `METHOD=AES-128,URI="key"` into its values.
+fn attributes(list: &str) -> HashMap<&str, &str> {
+    let mut attributes = HashMap::new();
+    let mut rest = list;
+    while let Some(eq) = rest.find('=') {
+        let name = rest[..eq].trim();
+        rest = &rest[eq + 1..];
+        let (value, next) = match rest.strip_prefix('"') {
+            Some(quoted) => {
+                let end = quoted.find('"').unwrap_or(quoted.len());
+                (&quoted[..end], quoted.get(end + 1..).unwrap_or(""))
+            }
+            None => {
+                let end = rest.find(',').unwrap_or(rest.len());
+                (&rest[..end], &rest[end..])
+            }
+        };
+        attributes.insert(name, value);
+        rest = next.trim_start_matches(',');
+    }
+    attributes
+}
+
+fn parse_iv(value: &str) -> Option<[u8; 16]> {
+    let hex = value.strip_prefix("0x").or_else(|| value.strip_prefix("0X"))?;
+    u128::from_str_radix(hex, 16).ok().map(u128::to_be_bytes)
+}
+
+fn parse(text: &str, base: &Url) -> Result<Playlist, AppError> {
+    let invalid = |what: &str| AppError::DecodeError(format!("Invalid HLS playlist: {}", what));
+    let mut lines = text.lines().map(str::trim).filter(|l| !l.is_empty());
+    if lines.next() != Some("#EXTM3U") {
+        return Err(invalid("missing #EXTM3U"));
+    }
+
+    let mut target_duration = 0.0;
+    let mut sequence = 0;
+    let mut ended = false;
+    let mut segments = Vec::new();
+    let mut duration = None;
+    let mut range = None;
+    let mut range_end = 0;
+    let mut key = None;
+    let mut map = None;
+    // Bandwidth of the `#EXT-X-STREAM-INF` waiting for its URI
+    let mut variant = None;
+    let mut best_variant: Option<(u64, Url)> = None;
+    let mut audio: Option<(bool, Url)> = None;
+
+    for line in lines {
+        let Some(tag) = line.strip_prefix('#') else {
+            let url = base.join(line).map_err(|_| invalid("bad URI"))?;
+            if let Some(bandwidth) = variant.take() {
+                if best_variant.as_ref().is_none_or(|(best, _)| bandwidth > *best) {
+                    best_variant = Some((bandwidth, url));
+                }
+            } else if let Some(duration) = duration.take() {
+                segments.push(Segment {
+                    sequence,
+                    url,
+                    duration,
+                    range: range.take(),
+                    key: key.clone(),
+                    map: map.clone(),
+                });
+                sequence += 1;
+            }
+            continue;
+        };
+        let (name, value) = tag.split_once(':').unwrap_or((tag, ""));
+        match name {
+            "EXT-X-STREAM-INF" => {
+                let bandwidth = attributes(value).get("BANDWIDTH").and_then(|b| b.parse().ok());
+                variant = Some(bandwidth.unwrap_or(0));
+            }
+            // Audio renditions beat variants, which may carry video as well
+            "EXT-X-MEDIA" => {
+                let attributes = attributes(value);
+                if let (Some(&"AUDIO"), Some(uri)) = (attributes.get("TYPE"), attributes.get("URI")) {
+                    let default = attributes.get("DEFAULT") == Some(&"YES");
+                    if audio.as_ref().is_none_or(|(chosen, _)| default && !chosen) {
+                        let url = base.join(uri).map_err(|_| invalid("bad URI"))?;
+                        audio = Some((default, url));
+                    }
+                }
+            }
+            "EXT-X-TARGETDURATION" => target_duration = value.parse().unwrap_or(0.0),
+            "EXT-X-MEDIA-SEQUENCE" => sequence = value.parse().unwrap_or(0),
+            "EXTINF" => {
+                let seconds = value.split(',').next().unwrap_or("");
+                duration = Some(seconds.trim().parse().map_err(|_| invalid("bad #EXTINF"))?);
+            }
+            "EXT-X-BYTERANGE" => {
+                let (length, offset) = value.split_once('@').unwrap_or((value, ""));
+                let length: u64 = length.parse().map_err(|_| invalid("bad #EXT-X-BYTERANGE"))?;
+                // Without an offset the sub-range follows the previous one
+                let offset = offset.parse().unwrap_or(range_end);
+                range_end = offset + length;
+                range = Some((length, offset));
+            }
+            "EXT-X-KEY" => {
+                let attributes = attributes(value);
+                key = match attributes.get("METHOD").copied() {
+                    Some("NONE") => None,
+                    Some("AES-128") => {
+                        let uri = attributes.get("URI").ok_or_else(|| invalid("key without URI"))?;
+                        Some(SegmentKey {
+                            uri: base.join(uri).map_err(|_| invalid("bad key URI"))?,
+                            iv: attributes.get("IV").and_then(|iv| parse_iv(iv)),
+                        })
+                    }
+                    _ => {
+                        return Err(AppError::DecodeError(
+                            "Unsupported HLS encryption method".to_string(),
+                        ))
+                    }
+                };
+            }
+            "EXT-X-MAP" => {
+                map = match attributes(value).get("URI") {
+                    Some(uri) => Some(base.join(uri).map_err(|_| invalid("bad URI"))?),
+                    None => None,
+                };
+            }
+            "EXT-X-ENDLIST" => ended = true,
+            _ => {}
+        }
+    }
+
+    if let Some((_, url)) = audio.or(best_variant.map(|(_, url)| (false, url))) {
+        return Ok(Playlist::Multivariant(url));
+    }
+    if segments.is_empty() && ended {
+        return Err(invalid("no segments"));
+    }
+    Ok(Playlist::Media(MediaPlaylist {
+        target_duration,
+        segments,
+        ended,
+    }))
+}
+
+async fn fetch_playlist(client: &Requester, url: &Url) -> Result<(Playlist, Url), AppError> {
+    let response = client.get(url.clone()).send().await?.error_for_status()?;
+    let url = response.url().clone();
+    let text = response.text().await?;
+    Ok((parse(&text, &url)?, url))
+}
+
+/// State shared between an `HlsSource` and the loader filling it.
+struct HlsShared {
+    /// Stands for the whole playlist towards the player: its duration, cancellation and
+    /// failure. The audio itself goes through the buffers handed to the loader.
+    root: Arc<StreamBuffer>,
+    memory_limit: u64,
+    /// Sequence number and start in seconds of every segment, for playlists that have ended.
+    starts: Option<Vec<(u64, f64)>>,
+    /// Segment to continue from and the buffer to fill from there on, set by a seek.
+    restart: Mutex<Option<(u64, Arc<StreamBuffer>)>>,
+    restarted: Notify,
+}
+
+impl HlsShared {
+    /// A buffer for the audio from some segment on. Seeking restarts the download, so
+    /// played bytes are never needed again.
+    fn buffer(&self) -> StreamBuffer {
+        StreamBuffer::new(None, Overflow::Discard(self.memory_limit))
+    }
+
+    fn segment_at(&self, pos: Duration) -> Option<(u64, f64)> {
+        let starts = self.starts.as_ref()?;
+        let pos = pos.as_secs_f64();
+        let index = starts.partition_point(|&(_, start)| start <= pos);
+        starts.get(index.saturating_sub(1)).copied()
+    }
+
+    fn segment_after(&self, sequence: u64) -> Option<u64> {
+        let starts = self.starts.as_ref()?;
+        starts.iter().map(|&(s, _)| s).find(|&s| s > sequence)
+    }
+}
+
+/// Opens the playlist `response` carries, choosing a media playlist from a multivariant one.
+/// The returned loader has to run for the source to get any data.
+pub async fn open(
+    client: Requester,
+    response: reqwest::Response,
+    memory_limit: u64,
+) -> Result<(HlsSource, HlsLoader), AppError> {
+    let mut url = response.url().clone();
+    let text = response.error_for_status()?.text().await?;
+    let mut playlist = parse(&text, &url)?;
+    if let Playlist::Multivariant(media) = &playlist {
+        let media = media.clone();
+        (playlist, url) = fetch_playlist(&client, &media).await?;
+    }
+    let Playlist::Media(playlist) = playlist else {
+        return Err(AppError::DecodeError("Nested HLS multivariant playlists".to_string()));
+    };
+
+    let root = Arc::new(StreamBuffer::new(None, Overflow::Unbounded));
+    let starts = playlist.ended.then(|| {
+        let mut start = 0.0;
+        playlist
+            .segments
+            .iter()
+            .map(|segment| {
+                let entry = (segment.sequence, start);
+                start += segment.duration;
+                entry
+            })
+            .collect::<Vec<_>>()
+    });
+    if playlist.ended {
+        let total = playlist.segments.iter().map(|s| s.duration).sum();
+        *root.duration.lock().unwrap() = Some(Duration::from_secs_f64(total));
+    }
+    let first = match playlist.ended {
+        true => 0,
+        false => playlist.segments.len().saturating_sub(LIVE_EDGE_SEGMENTS),
+    };
+    let next = playlist.segments.get(first).map_or(0, |s| s.sequence);
+    // Transport streams are demuxed to their bare audio, which probes without a hint
+    let extension = playlist
+        .segments
+        .first()
+        .and_then(|s| s.url.path().rsplit_once('.').map(|(_, ext)| ext.to_ascii_lowercase()))
+        .filter(|ext| ext != "ts");
+
+    let shared = Arc::new(HlsShared {
+        root,
+        memory_limit,
+        starts,
+        restart: Mutex::new(None),
+        restarted: Notify::new(),
+    });
+    let buffer = Arc::new(shared.buffer());
+    let source = HlsSource {
+        shared: shared.clone(),
+        buffer: buffer.clone(),
+        decoder: None,
+        extension,
+        segment: next,
+        skip: 0.0,
+        skip_samples: 0,
+        sample_rate: 44100,
+        channels: 2,
+    };
+    let loader = HlsLoader {
+        client,
+        url,
+        playlist,
+        shared,
+        buffer,
+        next,
+    };
+    Ok((source, loader))
+}
+
+/// Downloads segments in order into the source's buffer, refreshing live playlists.
+pub struct HlsLoader {
+    client: Requester,
+    url: Url,
+    playlist: MediaPlaylist,
+    shared: Arc<HlsShared>,
+    buffer: Arc<StreamBuffer>,
+    /// Sequence number of the next segment to download.
+    next: u64,
+}
+
+impl HlsLoader {
+    /// Runs until the source is dropped. An error ends the stream where it got to.
+    pub async fn run(mut self) -> Result<(), AppError> {
+        let root = self.shared.root.clone();
+        let result = tokio::select! {
+            result = self.download() => result,
+            _ = root.cancelled() => Ok(()),
+        };
+        if result.is_err() {
+            root.failed.store(true, Ordering::Relaxed);
+            self.buffer.is_ended.store(true, Ordering::Relaxed);
+            self.buffer.notify_data();
+        }
+        result
+    }
+
+    async fn download(&mut self) -> Result<(), AppError> {
+        let mut keys = HashMap::new();
+        let mut demuxer = Demuxer::default();
+        let mut offset: u64 = 0;
+        let mut written_map = None;
+
+        loop {
+            let restart = self.shared.restart.lock().unwrap().take();
+            if let Some((sequence, buffer)) = restart {
+                self.buffer = buffer;
+                self.next = sequence;
+                demuxer = Demuxer::default();
+                offset = 0;
+                written_map = None;
+            }
+
+            let segment = self
+                .playlist
+                .segments
+                .iter()
+                .find(|s| s.sequence >= self.next)
+                .cloned();
+            let Some(segment) = segment else {
+                if self.playlist.ended {
+                    self.buffer.is_ended.store(true, Ordering::Relaxed);
+                    self.buffer.notify_data();
+                    // Nothing left to do but wait for a seek
+                    self.shared.restarted.notified().await;
+                } else {
+                    let wait = Duration::from_secs_f64(self.playlist.target_duration.max(1.0));
+                    tokio::select! {
+                        _ = tokio::time::sleep(wait) => self.refresh().await?,
+                        _ = self.shared.restarted.notified() => {}
+                    }
+                }
+                continue;
+            };
+
+            // Stay within reach of the reader, the buffer drops the oldest bytes past its limit
+            if offset.saturating_sub(self.buffer.read_head()) > self.shared.memory_limit / 2 {
+                tokio::select! {
+                    _ = tokio::time::sleep(RETRY_DELAY) => {}
+                    _ = self.shared.restarted.notified() => {}
+                }
+                continue;
+            }
+            let data = tokio::select! {
+                data = self.fetch_segment(&segment, &mut keys) => data?,
+                _ = self.shared.restarted.notified() => continue,
+            };
+            if let Some(map) = segment.map.as_ref().filter(|&map| written_map.as_ref() != Some(map)) {
+                let init = self.fetch(map, None).await?;
+                self.buffer.write(offset, &init);
+                offset += init.len() as u64;
+                written_map = Some(map.clone());
+            }
+            let audio = demuxer.push(&data);
+            self.buffer.write(offset, &audio);
+            offset += audio.len() as u64;
+            self.buffer.notify_data();
+            self.next = segment.sequence + 1;
+        }
+    }
+
+    /// Reloads a live playlist, jumping ahead if playback fell behind its window. Failed
+    /// reloads are retried for at least a target duration, which the buffered segments cover.
+    async fn refresh(&mut self) -> Result<(), AppError> {
+        let give_up = Instant::now() + Duration::from_secs_f64(self.playlist.target_duration.max(1.0));
+        let mut attempt = 1;
+        let fetched = loop {
+            match fetch_playlist(&self.client, &self.url).await {
+                Ok(fetched) => break fetched,
+                Err(_) if attempt < SEGMENT_ATTEMPTS || Instant::now() < give_up => {
+                    attempt += 1;
+                    tokio::time::sleep(RETRY_DELAY).await;
+                }
+                Err(e) => return Err(e),
+            }
+        };
+        let (Playlist::Media(playlist), _) = fetched else {
+            return Err(AppError::DecodeError("HLS media playlist turned multivariant".to_string()));
+        };
+        if let Some(first) = playlist.segments.first() {
+            self.next = self.next.max(first.sequence);
+        }
+        self.playlist = playlist;
+        Ok(())
+    }
+
+    async fn fetch(&self, url: &Url, range: Option<(u64, u64)>) -> Result<Vec<u8>, AppError> {
+        let mut request = self.client.get(url.clone());
+        if let Some((length, offset)) = range {
+            let last = offset + length.max(1) - 1;
+            request = request.header(reqwest::header::RANGE, format!("bytes={}-{}", offset, last));
+        }
+        Ok(request.send().await?.error_for_status()?.bytes().await?.to_vec())
+    }
+
+    async fn fetch_segment(
+        &self,
+        segment: &Segment,
+        keys: &mut HashMap<Url, Vec<u8>>,
+    ) -> Result<Vec<u8>, AppError> {
+        let mut attempt = 1;
+        let data = loop {
+            match self.fetch(&segment.url, segment.range).await {
+                Ok(data) => break data,
+                Err(_) if attempt < SEGMENT_ATTEMPTS => {
+                    attempt += 1;
+                    tokio::time::sleep(RETRY_DELAY).await;
+                }
+                Err(e) => return Err(e),
+            }
+        };
+        let Some(key) = &segment.key else {
+            return Ok(data);
+        };
+
+        if !keys.contains_key(&key.uri) {
+            let bytes = self.fetch(&key.uri, None).await?;
+            keys.insert(key.uri.clone(), bytes);
+        }
+        let iv = segment.iv().unwrap_or_default();
+        let invalid = || AppError::DecodeError("Failed to decrypt HLS segment".to_string());
+        Aes128CbcDec::new_from_slices(&keys[&key.uri], &iv)
+            .map_err(|_| invalid())?
+            .decrypt_padded_vec_mut::<Pkcs7>(&data)
+            .map_err(|_| invalid())
+    }
+}
+
+/// Pulls the audio elementary stream out of MPEG-TS segments. Other segments, fragmented MP4
+/// and packed audio, pass through with their leading ID3 tags removed.
+#[derive(Default)]
+struct Demuxer {
+    pmt_pid: Option<u16>,
+    audio_pid: Option<u16>,
+}
+
+impl Demuxer {
+    fn push(&mut self, segment: &[u8]) -> Vec<u8> {
+        let is_transport_stream = segment.len() >= TS_PACKET
+            && segment[0] == 0x47
+            && segment.get(TS_PACKET).is_none_or(|&sync| sync == 0x47);
+        if is_transport_stream {
+            self.demux(segment)
+        } else {
+            strip_id3(segment).to_vec()
+        }
+    }
+
+    fn demux(&mut self, segment: &[u8]) -> Vec<u8> {
+        let mut audio = Vec::with_capacity(segment.len());
+        for packet in segment.chunks_exact(TS_PACKET) {
+            if packet[0] != 0x47 {
+                continue;
+            }
+            let unit_start = packet[1] & 0x40 != 0;
+            let pid = u16::from_be_bytes([packet[1] & 0x1f, packet[2]]);
+            let control = (packet[3] >> 4) & 0x3;
+            if control & 0x1 == 0 {
+                continue;
+            }
+            let mut payload = &packet[4..];
+            if control & 0x2 != 0 {
+                let length = payload[0] as usize;
+                payload = payload.get(1 + length..).unwrap_or(&[]);
+            }
+
+            if pid == 0 && unit_start {
+                self.pmt_pid = section(payload).and_then(pat_pmt_pid).or(self.pmt_pid);
+            } else if Some(pid) == self.pmt_pid && unit_start {
+                self.audio_pid = section(payload).and_then(pmt_audio_pid).or(self.audio_pid);
+            } else if Some(pid) == self.audio_pid {
+                if unit_start {
+                    payload = pes_payload(payload).unwrap_or(&[]);
+                }
+                audio.extend_from_slice(payload);
+            }
+        }
+        audio
+    }
+}
+
+/// The PSI section starting in a payload, without its trailing CRC.
+fn section(payload: &[u8]) -> Option<&[u8]> {
+    let pointer = *payload.first()? as usize;
+    let section = payload.get(1 + pointer..)?;
+    let length = (u16::from_be_bytes([*section.get(1)?, *section.get(2)?]) & 0x0fff) as usize;
+    section.get(..(3 + length).checked_sub(4)?)
+}
+
+/// PID of the first program's map in a program association table.
+fn pat_pmt_pid(section: &[u8]) -> Option<u16> {
+    section.get(8..)?.chunks_exact(4).find_map(|entry| {
+        let program = u16::from_be_bytes([entry[0], entry[1]]);
+        (program != 0).then(|| u16::from_be_bytes([entry[2] & 0x1f, entry[3]]))
+    })
+}
+
+/// PID of the first AAC or MPEG audio stream in a program map table.
+fn pmt_audio_pid(section: &[u8]) -> Option<u16> {
+    let info_length = (u16::from_be_bytes([*section.get(10)?, *section.get(11)?]) & 0x0fff) as usize;
+    let mut streams = section.get(12 + info_length..)?;
+    while streams.len() >= 5 {
+        let stream_type = streams[0];
+        let pid = u16::from_be_bytes([streams[1] & 0x1f, streams[2]]);
+        let es_info_length = (u16::from_be_bytes([streams[3], streams[4]]) & 0x0fff) as usize;
+        // ADTS AAC, MPEG-1 and MPEG-2 audio
+        if matches!(stream_type, 0x0f | 0x03 | 0x04) {
+            return Some(pid);
+        }
+        streams = streams.get(5 + es_info_length..)?;
+    }
+    None
+}
+
+/// Payload of a PES packet, past its header.
+fn pes_payload(packet: &[u8]) -> Option<&[u8]> {
+    if packet.get(..3)? != [0, 0, 1] {
+        return None;
+    }
+    let header_length = *packet.get(8)? as usize;
+    packet.get(9 + header_length..)
+}
+
+/// Skips the ID3 tags packed audio segments start with.
+fn strip_id3(mut data: &[u8]) -> &[u8] {
+    while data.len() >= 10 && data.starts_with(b"ID3") {
+        let size = data[6..10]
+            .iter()
+            .fold(0usize, |size, &byte| (size << 7) | (byte & 0x7f) as usize);
+        let footer = if data[5] & 0x10 != 0 { 10 } else { 0 };
+        data = data.get(10 + size + footer..).unwrap_or(&[]);
+    }
+    data
+}
+
+/// Decodes the segments of an HLS playlist as one continuous stream.
+pub struct HlsSource {
+    shared: Arc<HlsShared>,
+    buffer: Arc<StreamBuffer>,
+    decoder: Option<StreamDecoder>,
+    extension: Option<String>,
+    /// Segment the buffer starts at.
+    segment: u64,
+    /// Seconds into the first segment to drop once the decoder is open, after a seek.
+    skip: f64,
+    /// Samples still to drop before the seek target is reached.
+    skip_samples: usize,
+    sample_rate: u32,
+    channels: u16,
+}
+
+impl HlsSource {
+    /// Buffer standing for the playlist, see `HlsShared::root`.
+    pub fn stream(&self) -> Arc<StreamBuffer> {
+        self.shared.root.clone()
+    }
+
+    /// Has the loader continue from `sequence` into a fresh buffer, `skip` seconds of which
+    /// are dropped.
+    fn restart(&mut self, sequence: u64, skip: f64) {
+        let buffer = Arc::new(self.shared.buffer());
+        self.buffer.cancel();
+        self.buffer = buffer.clone();
+        self.decoder = None;
+        self.segment = sequence;
+        self.skip = skip;
+        self.skip_samples = 0;
+        *self.shared.restart.lock().unwrap() = Some((sequence, buffer));
+        self.shared.restarted.notify_one();
+    }
+}
+
+impl Drop for HlsSource {
+    fn drop(&mut self) {
+        self.buffer.cancel();
+        self.shared.root.cancel();
+    }
+}
+
+impl Iterator for HlsSource {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        if self.decoder.is_none() {
+            let decoder = StreamDecoder::open(self.buffer.clone(), self.extension.as_deref()).ok()?;
+            let frames = (self.skip * decoder.sample_rate() as f64) as usize;
+            self.skip_samples = frames * decoder.channels() as usize;
+            self.skip = 0.0;
+            self.decoder = Some(decoder);
+        }
+
+        let decoder = self.decoder.as_mut()?;
+        if self.skip_samples > 0 {
+            let mut ended = false;
+            for _ in 0..self.skip_samples.min(SKIP_PER_CALL) {
+                if decoder.next().is_none() {
+                    ended = true;
+                    break;
+                }
+                self.skip_samples -= 1;
+            }
+            self.sample_rate = decoder.sample_rate();
+            self.channels = decoder.channels();
+            if ended {
+                // The segment was shorter than the skip, carry on from the one after it
+                let next = self.shared.segment_after(self.segment)?;
+                self.restart(next, 0.0);
+            }
+            // Silence stands in for the dropped samples until the target is reached
+            return Some(0);
+        }
+        let sample = decoder.next();
+        self.sample_rate = decoder.sample_rate();
+        self.channels = decoder.channels();
+        sample
+    }
+}
+
+impl Source for HlsSource {
+    fn current_span_len(&self) -> Option<usize> {
+        self.decoder.as_ref().and_then(|d| d.current_span_len())
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        *self.shared.root.duration.lock().unwrap()
+    }
+
+    /// Restarts the download at the segment holding `pos`. Live playlists cannot seek, which
+    /// is a hard error: `NotSupported` would make the caller wait for the position to buffer.
+    fn try_seek(&mut self, pos: Duration) -> Result<(), rodio::source::SeekError> {
+        let (sequence, start) = self.shared.segment_at(pos).ok_or_else(|| {
+            rodio::source::SeekError::Other(Box::new(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "live HLS streams cannot seek",
+            )))
+        })?;
+        self.restart(sequence, (pos.as_secs_f64() - start).max(0.0));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn base() -> Url {
+        Url::parse("https://example.com/live/index.m3u8").unwrap()
+    }
+
+    fn media(text: &str) -> MediaPlaylist {
+        match parse(text, &base()) {
+            Ok(Playlist::Media(playlist)) => playlist,
+            _ => panic!("expected a media playlist"),
+        }
+    }
+
+    fn multivariant(text: &str) -> Url {
+        match parse(text, &base()) {
+            Ok(Playlist::Multivariant(url)) => url,
+            _ => panic!("expected a multivariant playlist"),
+        }
+    }
+
+    #[test]
+    fn multivariant_picks_highest_bandwidth() {
+        let url = multivariant(
+            "#EXTM3U\n\
+             #EXT-X-STREAM-INF:BANDWIDTH=64000,CODECS=\"mp4a.40.5\"\n\
+             low/index.m3u8\n\
+             #EXT-X-STREAM-INF:BANDWIDTH=256000\n\
+             high/index.m3u8\n\
+             #EXT-X-STREAM-INF:BANDWIDTH=128000\n\
+             mid/index.m3u8\n",
+        );
+        assert_eq!(url.as_str(), "https://example.com/live/high/index.m3u8");
+    }
+
+    #[test]
+    fn multivariant_prefers_default_audio_rendition() {
+        let url = multivariant(
+            "#EXTM3U\n\
+             #EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"a\",NAME=\"en\",URI=\"en.m3u8\"\n\
+             #EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"a\",NAME=\"main\",DEFAULT=YES,URI=\"main.m3u8\"\n\
+             #EXT-X-STREAM-INF:BANDWIDTH=256000,AUDIO=\"a\"\n\
+             video.m3u8\n",
+        );
+        assert_eq!(url.as_str(), "https://example.com/live/main.m3u8");
+    }
+
+    #[test]
+    fn media_playlist_segments() {
+        let playlist = media(
+            "#EXTM3U\n\
+             #EXT-X-TARGETDURATION:6\n\
+             #EXT-X-MEDIA-SEQUENCE:7\n\
+             #EXT-X-MAP:URI=\"init.mp4\"\n\
+             #EXTINF:6.0,\n\
+             #EXT-X-BYTERANGE:1000@0\n\
+             audio.mp4\n\
+             #EXTINF:5.5,title\n\
+             #EXT-X-BYTERANGE:500\n\
+             audio.mp4\n\
+             #EXTINF:4,\n\
+             https://cdn.example.com/last.aac\n\
+             #EXT-X-ENDLIST\n",
+        );
+        assert_eq!(playlist.target_duration, 6.0);
+        assert!(playlist.ended);
+
+        let segments = &playlist.segments;
+        assert_eq!(segments.len(), 3);
+        assert_eq!(segments.iter().map(|s| s.sequence).collect::<Vec<_>>(), [7, 8, 9]);
+        assert_eq!(segments[0].url.as_str(), "https://example.com/live/audio.mp4");
+        assert_eq!(segments[0].range, Some((1000, 0)));
+        // Without an offset the sub-range follows the previous one
+        assert_eq!(segments[1].range, Some((500, 1000)));
+        assert_eq!(segments[1].duration, 5.5);
+        assert_eq!(segments[2].url.as_str(), "https://cdn.example.com/last.aac");
+        assert_eq!(segments[2].range, None);
+        assert_eq!(
+            segments[0].map.as_ref().map(Url::as_str),
+            Some("https://example.com/live/init.mp4")
+        );
+    }
+
+    #[test]
+    fn live_playlist_is_not_ended() {
+        let playlist = media("#EXTM3U\n#EXT-X-TARGETDURATION:4\n#EXTINF:4,\na.ts\n");
+        assert!(!playlist.ended);
+    }
+
+    #[test]
+    fn rejects_missing_header_and_unknown_encryption() {
+        assert!(parse("#EXTINF:4,\na.ts\n", &base()).is_err());
+        let sample_aes = "#EXTM3U\n#EXT-X-KEY:METHOD=SAMPLE-AES,URI=\"k\"\n#EXTINF:4,\na.ts\n";
+        assert!(parse(sample_aes, &base()).is_err());
+    }
+
+    #[test]
+    fn key_iv_defaults_to_sequence_number() {
+        let playlist = media(
+            "#EXTM3U\n\
+             #EXT-X-MEDIA-SEQUENCE:41\n\
+             #EXT-X-KEY:METHOD=AES-128,URI=\"key.bin\"\n\
+             #EXTINF:4,\n\
+             a.ts\n\
+             #EXT-X-KEY:METHOD=AES-128,URI=\"key.bin\",IV=0x000102030405060708090A0B0C0D0E0F\n\
+             #EXTINF:4,\n\
+             b.ts\n\
+             #EXT-X-KEY:METHOD=NONE\n\
+             #EXTINF:4,\n\
+             c.ts\n",
+        );
+        let segments = &playlist.segments;
+        assert_eq!(
+            segments[0].key.as_ref().map(|k| k.uri.as_str()),
+            Some("https://example.com/live/key.bin")
+        );
+        assert_eq!(segments[0].iv(), Some(41u128.to_be_bytes()));
+        assert_eq!(
+            segments[1].iv(),
+            Some([0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15])
+        );
+        assert_eq!(segments[2].iv(), None);
+    }
+
+    /// A transport stream packet carrying `payload`, padded with adaptation field stuffing.
+    fn ts_packet(pid: u16, unit_start: bool, payload: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0x47, (unit_start as u8) << 6 | (pid >> 8) as u8, pid as u8];
+        let stuffing = TS_PACKET - 4 - payload.len();
+        if stuffing == 0 {
+            packet.push(0x10);
+        } else {
+            packet.push(0x30);
+            packet.push((stuffing - 1) as u8);
+            if stuffing > 1 {
+                packet.push(0);
+                packet.extend(std::iter::repeat_n(0xff, stuffing - 2));
+            }
+        }
+        packet.extend_from_slice(payload);
+        assert_eq!(packet.len(), TS_PACKET);
+        packet
+    }
+
+    /// A PSI section behind a zero pointer field, with a dummy CRC.
+    fn psi(table_id: u8, body: &[u8]) -> Vec<u8> {
+        let length = body.len() + 4;
+        let mut section = vec![0, table_id, 0xb0 | (length >> 8) as u8, length as u8];
+        section.extend_from_slice(body);
+        section.extend_from_slice(&[0; 4]);
+        section
+    }
+
+    fn program_tables(pmt_pid: u16, audio_pid: u16) -> Vec<u8> {
+        let pat = psi(0x00, &[0, 1, 0xc1, 0, 0, 0, 1, 0xe0 | (pmt_pid >> 8) as u8, pmt_pid as u8]);
+        let pmt = psi(
+            0x02,
+            &[
+                0, 1, 0xc1, 0, 0, 0xe1, 0x00, 0xf0, 0,
+                // A video stream first, then ADTS AAC
+                0x1b, 0xe1, 0x00, 0xf0, 0,
+                0x0f, 0xe0 | (audio_pid >> 8) as u8, audio_pid as u8, 0xf0, 0,
+            ],
+        );
+        let mut tables = ts_packet(0, true, &pat);
+        tables.extend(ts_packet(pmt_pid, true, &pmt));
+        tables
+    }
+
+    #[test]
+    fn demuxer_reassembles_pes_across_packets() {
+        let audio: Vec<u8> = (0..300).map(|i| i as u8).collect();
+        let mut pes = vec![0, 0, 1, 0xc0, 0, 0, 0x80, 0x80, 5, 0x21, 0, 1, 0, 1];
+        pes.extend_from_slice(&audio);
+        let (first, rest) = pes.split_at(TS_PACKET - 4);
+
+        let mut segment = program_tables(0x1000, 0x101);
+        segment.extend(ts_packet(0x101, true, first));
+        segment.extend(ts_packet(0x100, true, &[0xee; 20]));
+        segment.extend(ts_packet(0x101, false, rest));
+
+        let mut demuxer = Demuxer::default();
+        assert_eq!(demuxer.push(&segment), audio);
+
+        // The program tables carry over to segments that do not repeat them
+        let mut next = vec![0, 0, 1, 0xc0, 0, 0, 0x80, 0x00, 0];
+        next.extend_from_slice(&[7; 10]);
+        assert_eq!(demuxer.push(&ts_packet(0x101, true, &next)), [7; 10]);
+    }
+
+    #[test]
+    fn packed_audio_loses_id3_tags() {
+        let mut segment = b"ID3\x04\x00\x00\x00\x00\x00\x02ab".to_vec();
+        segment.extend_from_slice(&[0xff, 0xf1, 1, 2]);
+        assert_eq!(Demuxer::default().push(&segment), [0xff, 0xf1, 1, 2]);
+    }
+}
@@ -0,0 +1,184 @@
+use crate::error::AppError;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, COOKIE, USER_AGENT};
+use reqwest::{Client, IntoUrl, RequestBuilder};
+use serde::{Deserialize, Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const MIN_CONNECT_TIMEOUT: u32 = 1;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HttpSettings {
+    /// Sent with every request that does not bring its own.
+    pub user_agent: String,
+    /// Seconds to wait for a connection to a server.
+    pub connect_timeout: u32,
+}
+
+impl Default for HttpSettings {
+    fn default() -> Self {
+        HttpSettings {
+            user_agent: concat!("cicadas/", env!("CARGO_PKG_VERSION")).to_string(),
+            connect_timeout: 15,
+        }
+    }
+}
+
+/// The client every stream download goes through, so connections to the same CDN are pooled
+/// across tracks.
+pub struct HttpClient {
+    settings: Mutex<HttpSettings>,
+    client: Mutex<Client>,
+}
+
+impl HttpClient {
+    pub fn new(settings: HttpSettings) -> Result<Self, AppError> {
+        let client = build_client(&settings)?;
+        Ok(HttpClient {
+            settings: Mutex::new(settings),
+            client: Mutex::new(client),
+        })
+    }
+
+    pub fn settings(&self) -> HttpSettings {
+        self.settings.lock().unwrap().clone()
+    }
+
+    /// Replaces the client. Downloads already running keep the old one until they finish.
+    pub fn configure(&self, settings: HttpSettings) -> Result<(), AppError> {
+        if settings.connect_timeout < MIN_CONNECT_TIMEOUT {
+            return Err(AppError::InvalidOperation(format!(
+                "Connect timeout must be at least {} second",
+                MIN_CONNECT_TIMEOUT
+            )));
+        }
+        let client = build_client(&settings)?;
+        *self.client.lock().unwrap() = client;
+        *self.settings.lock().unwrap() = settings;
+        Ok(())
+    }
+
+    /// Client and options for the requests of one track.
+    pub fn requester(&self, options: &RequestOptions) -> Result<Requester, AppError> {
+        Ok(Requester {
+            client: self.client.lock().unwrap().clone(),
+            headers: Arc::new(options.header_map()?),
+            auth: options.auth.clone(),
+        })
+    }
+}
+
+impl Default for HttpClient {
+    fn default() -> Self {
+        HttpClient::new(HttpSettings::default()).expect("failed to build HTTP client")
+    }
+}
+
+fn build_client(settings: &HttpSettings) -> Result<Client, AppError> {
+    Ok(Client::builder()
+        .user_agent(settings.user_agent.as_str())
+        .connect_timeout(Duration::from_secs(settings.connect_timeout as u64))
+        .build()?)
+}
+
+/// Request details some hosts insist on before serving audio, such as a `Referer`, login
+/// cookies or a browser `User-Agent`.
+///
+/// Credentials are never serialized, so the queue saved with a session or an alarm does not
+/// keep them in plain text. Tracks restored from disk play without them.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RequestOptions {
+    #[serde(serialize_with = "serialize_public_headers")]
+    pub headers: BTreeMap<String, String>,
+    /// Sent together as one `Cookie` header.
+    #[serde(skip_serializing)]
+    pub cookies: BTreeMap<String, String>,
+    /// Overrides the client's user agent.
+    pub user_agent: Option<String>,
+    #[serde(skip_serializing)]
+    pub auth: Option<HttpAuth>,
+}
+
+/// Headers that carry credentials when passed in `RequestOptions::headers` directly.
+const SECRET_HEADERS: [&str; 3] = ["authorization", "cookie", "proxy-authorization"];
+
+fn serialize_public_headers<S: Serializer>(
+    headers: &BTreeMap<String, String>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(headers.iter().filter(|(name, _)| {
+        !SECRET_HEADERS
+            .iter()
+            .any(|secret| name.eq_ignore_ascii_case(secret))
+    }))
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum HttpAuth {
+    Basic {
+        username: String,
+        #[serde(default)]
+        password: Option<String>,
+    },
+    Bearer {
+        token: String,
+    },
+}
+
+impl RequestOptions {
+    pub fn is_empty(&self) -> bool {
+        *self == RequestOptions::default()
+    }
+
+    fn header_map(&self) -> Result<HeaderMap, AppError> {
+        let mut map = HeaderMap::new();
+        for (name, value) in &self.headers {
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .map_err(|_| AppError::InvalidOperation(format!("Invalid header name: {}", name)))?;
+            map.insert(name, header_value(value)?);
+        }
+        if !self.cookies.is_empty() {
+            let cookies = self
+                .cookies
+                .iter()
+                .map(|(name, value)| format!("{}={}", name, value))
+                .collect::<Vec<_>>()
+                .join("; ");
+            map.insert(COOKIE, header_value(&cookies)?);
+        }
+        if let Some(user_agent) = &self.user_agent {
+            map.insert(USER_AGENT, header_value(user_agent)?);
+        }
+        Ok(map)
+    }
+}
+
+fn header_value(value: &str) -> Result<HeaderValue, AppError> {
+    HeaderValue::from_str(value)
+        .map_err(|_| AppError::InvalidOperation(format!("Invalid header value: {}", value)))
+}
+
+/// The shared client with the headers and auth of one track, applied to each of its requests.
+#[derive(Clone)]
+pub struct Requester {
+    client: Client,
+    headers: Arc<HeaderMap>,
+    auth: Option<HttpAuth>,
+}
+
+impl Requester {
+    pub fn get<U: IntoUrl>(&self, url: U) -> RequestBuilder {
+        let request = self.client.get(url).headers((*self.headers).clone());
+        match &self.auth {
+            Some(HttpAuth::Basic { username, password }) => {
+                request.basic_auth(username, password.as_ref())
+            }
+            Some(HttpAuth::Bearer { token }) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+}
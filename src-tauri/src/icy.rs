@@ -0,0 +1,103 @@
+use reqwest::header::HeaderMap;
+
+/// Header asking Shoutcast and Icecast servers to interleave metadata with the audio.
+pub const REQUEST_HEADER: (&str, &str) = ("Icy-MetaData", "1");
+
+/// Whether the server is a Shoutcast or Icecast station, whose stream never ends.
+pub fn is_radio(headers: &HeaderMap) -> bool {
+    headers.keys().any(|name| name.as_str().starts_with("icy-"))
+}
+
+/// Pulls the metadata blocks an ICY server sends every `metaint` audio bytes out of the stream.
+/// Each block is a length byte, in units of 16, followed by text like `StreamTitle='...';`.
+pub struct IcyDemuxer {
+    metaint: usize,
+    /// Audio bytes left before the next length byte.
+    audio_left: usize,
+    /// Size of the block being read, once its length byte arrived.
+    block_len: Option<usize>,
+    block: Vec<u8>,
+}
+
+impl IcyDemuxer {
+    /// `None` unless the server agreed to send metadata.
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let metaint = headers
+            .get("icy-metaint")?
+            .to_str()
+            .ok()?
+            .trim()
+            .parse()
+            .ok()
+            .filter(|&metaint: &usize| metaint > 0)?;
+        Some(IcyDemuxer {
+            metaint,
+            audio_left: metaint,
+            block_len: None,
+            block: Vec::new(),
+        })
+    }
+
+    /// Appends the audio in `chunk` to `audio`, returning the title of the last complete block
+    /// in it that carried one.
+    pub fn push(&mut self, mut chunk: &[u8], audio: &mut Vec<u8>) -> Option<String> {
+        let mut title = None;
+        while !chunk.is_empty() {
+            if self.audio_left > 0 {
+                let take = self.audio_left.min(chunk.len());
+                audio.extend_from_slice(&chunk[..take]);
+                self.audio_left -= take;
+                chunk = &chunk[take..];
+                continue;
+            }
+
+            let block_len = match self.block_len {
+                Some(block_len) => block_len,
+                None => {
+                    let block_len = chunk[0] as usize * 16;
+                    chunk = &chunk[1..];
+                    self.block_len = Some(block_len);
+                    block_len
+                }
+            };
+            let take = (block_len - self.block.len()).min(chunk.len());
+            self.block.extend_from_slice(&chunk[..take]);
+            chunk = &chunk[take..];
+
+            if self.block.len() == block_len {
+                if block_len > 0 {
+                    title = stream_title(&self.block).or(title);
+                }
+                self.block.clear();
+                self.block_len = None;
+                self.audio_left = self.metaint;
+            }
+        }
+        title
+    }
+}
+
+/// Value of `StreamTitle` in a metadata block, which may itself contain quotes.
+fn stream_title(block: &[u8]) -> Option<String> {
+    let text = decode(block);
+    let start = text.find("StreamTitle='")? + "StreamTitle='".len();
+    let rest = &text[start..];
+    let end = rest.find("';").unwrap_or_else(|| rest.trim_end_matches('\0').len());
+    Some(rest[..end].trim().to_string())
+}
+
+/// Metadata is UTF-8 on most servers, older ones send Latin-1.
+fn decode(block: &[u8]) -> String {
+    match std::str::from_utf8(block) {
+        Ok(text) => text.to_string(),
+        Err(_) => block.iter().map(|&byte| byte as char).collect(),
+    }
+}
+
+/// Splits the common `Artist - Title` form of a stream title.
+pub fn split_title(title: &str) -> (Option<&str>, &str) {
+    match title.split_once(" - ") {
+        Some((artist, title)) => (Some(artist.trim()), title.trim()),
+        None => (None, title),
+    }
+}
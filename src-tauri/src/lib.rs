@@ -1,15 +1,44 @@
+mod alarm;
+mod analyzer;
 mod audio;
+mod biquad;
+mod cache;
+mod channels;
+mod equalizer;
 mod error;
+mod fade;
+mod hls;
+mod http;
+mod icy;
 mod local_scanner;
+mod loudness;
 mod media_control;
+mod output;
+mod queue;
+mod session;
+mod sleep_timer;
+mod stream;
+mod stretch;
+mod waveform;
 
-use audio::AudioState;
+use alarm::AlarmState;
+use analyzer::AnalyzerState;
+use audio::{AudioState, BufferSettings, CrossfadeSettings, LoadedTracks, NormalizationSettings};
+use cache::StreamCache;
+use channels::ChannelControl;
+use equalizer::EqualizerControl;
+use fade::TransportFade;
+use http::HttpClient;
+use loudness::LoudnessStore;
 use media_control::MediaControlState;
-use rodio::OutputStreamBuilder;
-use std::sync::atomic::AtomicBool;
-use std::sync::{Arc, Condvar, Mutex, Once};
-use std::time::Duration;
-use tauri::{image::Image, Emitter, Manager};
+use output::OutputState;
+use queue::PlayQueue;
+use session::SessionState;
+use sleep_timer::{SleepTimerState, SleepTimerStatus};
+use stretch::TempoControl;
+use waveform::WaveformStore;
+use std::sync::{Arc, Mutex, Once};
+use tauri::{image::Image, Emitter, Listener, Manager};
 use tauri::{
     menu::{MenuBuilder, MenuItemBuilder},
     tray::{MouseButton, MouseButtonState, TrayIconBuilder, TrayIconEvent},
@@ -18,21 +47,33 @@ use tauri::{
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 #[tokio::main]
 pub async fn run() {
-    let stream = OutputStreamBuilder::open_default_stream().unwrap();
+    let output_state = OutputState::default();
     let audio_state = AudioState {
         sink: Arc::new(Mutex::new(None)),
-        stream: stream.mixer(),
-        buffer: Arc::new(Mutex::new(Vec::new())),
-        is_stream_ended: Arc::new(AtomicBool::new(false)),
-        decoder: Arc::new(Mutex::new(None)),
-        data_available: Arc::new((Mutex::new(false), Condvar::new())),
+        stream: output_state.mixer(),
         seek_target: Arc::new(Mutex::new(None)),
-        buffered_duration: Arc::new(Mutex::new(Duration::from_secs(0))),
         progress_tx: Arc::new(Mutex::new(None)),
+        queue: Arc::new(Mutex::new(PlayQueue::default())),
+        tracks: Arc::new(Mutex::new(LoadedTracks::default())),
+        crossfade: Arc::new(Mutex::new(CrossfadeSettings::default())),
+        outgoing: Arc::new(Mutex::new(Vec::new())),
+        normalization: Arc::new(Mutex::new(NormalizationSettings::default())),
+        loudness: Arc::new(Mutex::new(LoudnessStore::default())),
+        equalizer: Arc::new(EqualizerControl::default()),
+        tempo: Arc::new(TempoControl::default()),
+        channels: Arc::new(ChannelControl::default()),
+        transport: Arc::new(TransportFade::default()),
+        cache: Arc::new(Mutex::new(StreamCache::default())),
+        buffering: Arc::new(Mutex::new(BufferSettings::default())),
+        http: Arc::new(HttpClient::default()),
+        waveforms: Arc::new(Mutex::new(WaveformStore::default())),
     };
+    let analyzer_state = AnalyzerState::new(output_state.meter_tap());
     let media_control_state = MediaControlState {
         media_controls: Arc::new(Mutex::new(None)),
         init_once: Once::new(),
+        metadata: Mutex::new(Default::default()),
+        stream_title: Mutex::new(None),
     };
 
     tauri::Builder::default()
@@ -46,15 +87,49 @@ pub async fn run() {
         .plugin(tauri_plugin_shell::init())
         .manage(audio_state)
         .manage(media_control_state)
+        .manage(output_state)
+        .manage(analyzer_state)
+        .manage(SleepTimerState::default())
+        .manage(AlarmState::default())
+        .manage(SessionState::default())
         .setup(|app| {
+            let data_dir = app.path().app_data_dir().ok();
+            if let Some(dir) = &data_dir {
+                app.state::<AudioState>().loudness.lock().unwrap().load(dir);
+            }
+            if let Ok(dir) = app.path().app_cache_dir() {
+                let state = app.state::<AudioState>();
+                state.cache.lock().unwrap().load(&dir.join("streams"));
+                state.waveforms.lock().unwrap().load(&dir.join("waveforms"));
+            }
+            app.state::<OutputState>()
+                .start(app.handle().clone(), data_dir.as_deref());
+            app.state::<AlarmState>()
+                .start(app.handle().clone(), data_dir.as_deref());
+            app.state::<SessionState>()
+                .start(app.handle().clone(), data_dir.as_deref());
+
             let show = MenuItemBuilder::with_id("show", "Show").build(app)?;
             let pause_resume =
                 MenuItemBuilder::with_id("pause_resume", "Pause/Resume").build(app)?;
+            let timer = MenuItemBuilder::with_id(
+                "sleep_timer",
+                sleep_timer::tray_label(&SleepTimerStatus::default()),
+            )
+            .enabled(false)
+            .build(app)?;
             let quit = MenuItemBuilder::with_id("quit", "Quit").build(app)?;
             let menu = MenuBuilder::new(app)
-                .items(&[&show, &pause_resume, &quit])
+                .items(&[&show, &pause_resume, &timer, &quit])
                 .build()?;
 
+            app.listen("sleep_timer_event", move |event| {
+                if let Ok(status) = serde_json::from_str::<SleepTimerStatus>(event.payload()) {
+                    let _ = timer.set_text(sleep_timer::tray_label(&status));
+                    let _ = timer.set_enabled(status.mode.is_some());
+                }
+            });
+
             let _tray = TrayIconBuilder::new()
                 .title("Cicadas")
                 .tooltip("Cicadas")
@@ -70,7 +145,9 @@ pub async fn run() {
                     "pause_resume" => {
                         let _ = app.emit("media-control", "toggle");
                     }
+                    "sleep_timer" => sleep_timer::cancel(app),
                     "quit" => {
+                        session::save(app);
                         std::process::exit(0);
                     }
                     _ => (),
@@ -108,8 +185,56 @@ pub async fn run() {
             audio::set_volume,
             audio::get_volume,
             audio::set_speed,
+            audio::set_speed_mode,
+            audio::get_speed_mode,
+            audio::set_pitch_shift,
+            audio::get_pitch_shift,
             audio::set_playback_progress,
+            audio::set_channel_settings,
+            audio::get_channel_settings,
+            audio::set_transport_fades,
+            audio::get_transport_fades,
             audio::get_playback_progress,
+            audio::set_queue,
+            audio::enqueue,
+            audio::clear_queue,
+            audio::get_queue,
+            audio::set_repeat_mode,
+            audio::set_shuffle_mode,
+            audio::play_queue_index,
+            audio::skip_next,
+            audio::skip_previous,
+            audio::set_crossfade,
+            audio::get_crossfade,
+            audio::set_normalization,
+            audio::set_buffer_settings,
+            audio::get_buffer_settings,
+            audio::set_http_settings,
+            audio::get_http_settings,
+            audio::get_normalization,
+            loudness::analyze_loudness,
+            waveform::get_waveform,
+            cache::get_stream_cache,
+            cache::clear_stream_cache,
+            cache::set_stream_cache_limit,
+            equalizer::get_equalizer,
+            equalizer::set_equalizer,
+            equalizer::get_equalizer_presets,
+            equalizer::apply_equalizer_preset,
+            output::list_output_devices,
+            output::set_output_device,
+            output::get_output_device,
+            analyzer::subscribe_meter,
+            analyzer::unsubscribe_meter,
+            analyzer::set_meter_settings,
+            analyzer::get_meter_settings,
+            sleep_timer::set_sleep_timer,
+            sleep_timer::cancel_sleep_timer,
+            sleep_timer::get_sleep_timer,
+            alarm::schedule_alarm,
+            alarm::cancel_alarm,
+            alarm::get_alarms,
+            alarm::take_missed_alarms,
             media_control::init_media_controls,
             media_control::update_media_metadata,
             media_control::update_playback_status,
@@ -2,7 +2,7 @@ use base64::{engine::general_purpose, Engine as _};
 use lofty::{
     file::{AudioFile, TaggedFileExt},
     probe::Probe,
-    tag::{Accessor, ItemKey},
+    tag::{Accessor, ItemKey, Tag},
 };
 use serde::{Deserialize, Serialize};
 use std::fs;
@@ -12,7 +12,11 @@ use std::sync::{Arc, Mutex};
 use std::thread;
 use walkdir::WalkDir;
 
+/// Offset from the R128 reference level (-23 LUFS) to the ReplayGain one (-18 LUFS).
+const R128_TO_REPLAYGAIN_DB: f32 = 5.0;
+
 #[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
 pub struct Song {
     id: String,
     name: String,
@@ -24,6 +28,17 @@ pub struct Song {
     storage: String,
     mtime: u64,
     path: String,
+    replay_gain: Option<ReplayGain>,
+}
+
+/// Gains in dB relative to the ReplayGain reference level, peaks linear.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplayGain {
+    pub track_gain: Option<f32>,
+    pub track_peak: Option<f32>,
+    pub album_gain: Option<f32>,
+    pub album_peak: Option<f32>,
 }
 
 #[tauri::command]
@@ -122,6 +137,7 @@ fn process_file(path: &Path) -> Option<Song> {
     let artist = tag.and_then(|t| t.artist().map(|s| s.to_string()));
     let album = tag.and_then(|t| t.album().map(|s| s.to_string()));
     let lyrics = tag.and_then(|t| t.get_string(&ItemKey::Lyrics).map(|s| s.to_string()));
+    let replay_gain = tag.and_then(read_replay_gain);
 
     let cover = tag.and_then(|t| t.pictures().first()).map(|p| {
         let b64 = general_purpose::STANDARD.encode(&p.data());
@@ -153,9 +169,40 @@ fn process_file(path: &Path) -> Option<Song> {
         mtime,
         lyrics,
         path: path.to_string_lossy().into_owned(),
+        replay_gain,
     })
 }
 
+/// Reads ReplayGain tags, falling back to Opus-style R128 gains.
+fn read_replay_gain(tag: &Tag) -> Option<ReplayGain> {
+    let decibels = |key: ItemKey| {
+        tag.get_string(&key)
+            .and_then(|s| s.trim().trim_end_matches("dB").trim().parse::<f32>().ok())
+    };
+    let peak = |key: ItemKey| tag.get_string(&key).and_then(|s| s.trim().parse::<f32>().ok());
+    let r128 = |key: &str| {
+        tag.get_string(&ItemKey::Unknown(key.to_string()))
+            .and_then(|s| s.trim().parse::<i16>().ok())
+            .map(|q| q as f32 / 256.0 + R128_TO_REPLAYGAIN_DB)
+    };
+
+    let gain = ReplayGain {
+        track_gain: decibels(ItemKey::ReplayGainTrackGain).or_else(|| r128("R128_TRACK_GAIN")),
+        track_peak: peak(ItemKey::ReplayGainTrackPeak),
+        album_gain: decibels(ItemKey::ReplayGainAlbumGain).or_else(|| r128("R128_ALBUM_GAIN")),
+        album_peak: peak(ItemKey::ReplayGainAlbumPeak),
+    };
+    (gain.track_gain.is_some() || gain.album_gain.is_some()).then_some(gain)
+}
+
+pub fn probe_replay_gain(path: &Path) -> Option<ReplayGain> {
+    let tagged_file = Probe::open(path).and_then(|pb| pb.read()).ok()?;
+    let tag = tagged_file
+        .primary_tag()
+        .or_else(|| tagged_file.first_tag())?;
+    read_replay_gain(tag)
+}
+
 #[tauri::command]
 pub fn get_song_buffer(path: &str) -> Result<Vec<u8>, String> {
     fs::read(path).map_err(|e| e.to_string())
@@ -0,0 +1,235 @@
+use crate::audio::AudioState;
+use crate::biquad::Biquad;
+use crate::error::AppError;
+use crate::local_scanner;
+use rodio::{Decoder, Sample, Source};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::f64::consts::PI;
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tauri::State;
+
+const STORE_FILE: &str = "loudness.json";
+const ABSOLUTE_GATE_LUFS: f64 = -70.0;
+const RELATIVE_GATE_LU: f64 = -10.0;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct LoudnessEntry {
+    pub mtime: u64,
+    /// Integrated loudness in LUFS.
+    pub loudness: f64,
+    /// Sample peak, linear.
+    pub peak: f32,
+}
+
+/// Integrated loudness measured for untagged files, persisted in the app data directory.
+#[derive(Default)]
+pub struct LoudnessStore {
+    entries: HashMap<String, LoudnessEntry>,
+    measuring: HashSet<String>,
+    file: Option<PathBuf>,
+}
+
+impl LoudnessStore {
+    pub fn load(&mut self, dir: &Path) {
+        let file = dir.join(STORE_FILE);
+        if let Ok(data) = fs::read(&file) {
+            self.entries = serde_json::from_slice(&data).unwrap_or_default();
+        }
+        self.file = Some(file);
+    }
+
+    pub fn get(&self, path: &str, mtime: u64) -> Option<LoudnessEntry> {
+        self.entries.get(path).copied().filter(|e| e.mtime == mtime)
+    }
+
+    fn insert(&mut self, path: String, entry: LoudnessEntry) {
+        self.entries.insert(path, entry);
+        if let Some(file) = &self.file {
+            if let Some(dir) = file.parent() {
+                let _ = fs::create_dir_all(dir);
+            }
+            if let Ok(data) = serde_json::to_vec(&self.entries) {
+                let _ = fs::write(file, data);
+            }
+        }
+    }
+}
+
+pub fn file_mtime(path: &Path) -> Option<u64> {
+    fs::metadata(path)
+        .ok()?
+        .modified()
+        .ok()?
+        .duration_since(std::time::UNIX_EPOCH)
+        .ok()
+        .map(|d| d.as_secs())
+}
+
+/// Returns the stored measurement for `path`, queueing a background measurement when it is
+/// missing and `measure` is set.
+pub fn lookup(store: &Arc<Mutex<LoudnessStore>>, path: &Path, measure: bool) -> Option<LoudnessEntry> {
+    let mtime = file_mtime(path)?;
+    let key = path.to_string_lossy().into_owned();
+    if let Some(entry) = store.lock().unwrap().get(&key, mtime) {
+        return Some(entry);
+    }
+    if measure {
+        spawn_measurement(store.clone(), path.to_path_buf());
+    }
+    None
+}
+
+fn spawn_measurement(store: Arc<Mutex<LoudnessStore>>, path: PathBuf) {
+    let key = path.to_string_lossy().into_owned();
+    if !store.lock().unwrap().measuring.insert(key.clone()) {
+        return;
+    }
+
+    thread::spawn(move || {
+        let entry = file_mtime(&path).and_then(|mtime| {
+            measure_file(&path).ok().map(|(loudness, peak)| LoudnessEntry {
+                mtime,
+                loudness,
+                peak,
+            })
+        });
+
+        let mut store = store.lock().unwrap();
+        store.measuring.remove(&key);
+        if let Some(entry) = entry {
+            store.insert(key, entry);
+        }
+    });
+}
+
+/// Decodes the whole file and returns its EBU R128 integrated loudness and sample peak.
+pub fn measure_file(path: &Path) -> Result<(f64, f32), AppError> {
+    let file = File::open(path).map_err(|e| AppError::FileOpenError(e.to_string()))?;
+    let decoder =
+        Decoder::new(BufReader::new(file)).map_err(|e| AppError::DecodeError(e.to_string()))?;
+
+    let mut meter = LoudnessMeter::new(decoder.channels() as usize, decoder.sample_rate());
+    for sample in decoder {
+        meter.push(sample.to_f32());
+    }
+
+    meter
+        .integrated()
+        .map(|loudness| (loudness, meter.peak))
+        .ok_or_else(|| AppError::DecodeError("Track is too short or silent to measure".to_string()))
+}
+
+/// K-weighting pre-filter from ITU-R BS.1770, computed for an arbitrary sample rate.
+fn k_weighting(sample_rate: u32) -> [Biquad; 2] {
+    let rate = sample_rate as f64;
+
+    let f0 = 1681.974450955533;
+    let gain = 3.999843853973347;
+    let q = 0.7071752369554196;
+    let k = (PI * f0 / rate).tan();
+    let vh = 10f64.powf(gain / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let shelf = Biquad::new(
+        [vh + vb * k / q + k * k, 2.0 * (k * k - vh), vh - vb * k / q + k * k],
+        [1.0 + k / q + k * k, 2.0 * (k * k - 1.0), 1.0 - k / q + k * k],
+    );
+
+    let f0 = 38.13547087602444;
+    let q = 0.5003270373238773;
+    let k = (PI * f0 / rate).tan();
+    let high_pass = Biquad::new(
+        [1.0, -2.0, 1.0],
+        [1.0 + k / q + k * k, 2.0 * (k * k - 1.0), 1.0 - k / q + k * k],
+    );
+
+    [shelf, high_pass]
+}
+
+struct LoudnessMeter {
+    filters: Vec<[Biquad; 2]>,
+    channel: usize,
+    step_frames: usize,
+    frames: usize,
+    energy: f64,
+    /// Channel-summed mean square of each 100 ms step.
+    steps: Vec<f64>,
+    peak: f32,
+}
+
+impl LoudnessMeter {
+    fn new(channels: usize, sample_rate: u32) -> Self {
+        LoudnessMeter {
+            filters: vec![k_weighting(sample_rate); channels.max(1)],
+            channel: 0,
+            step_frames: (sample_rate as usize / 10).max(1),
+            frames: 0,
+            energy: 0.0,
+            steps: Vec::new(),
+            peak: 0.0,
+        }
+    }
+
+    fn push(&mut self, sample: f32) {
+        self.peak = self.peak.max(sample.abs());
+
+        let [shelf, high_pass] = &mut self.filters[self.channel];
+        let weighted = high_pass.process(shelf.process(sample as f64));
+        self.energy += weighted * weighted;
+
+        self.channel += 1;
+        if self.channel == self.filters.len() {
+            self.channel = 0;
+            self.frames += 1;
+            if self.frames == self.step_frames {
+                self.steps.push(self.energy / self.frames as f64);
+                self.energy = 0.0;
+                self.frames = 0;
+            }
+        }
+    }
+
+    /// Gated integrated loudness over 400 ms blocks with 75% overlap.
+    fn integrated(&self) -> Option<f64> {
+        let blocks: Vec<f64> = self
+            .steps
+            .windows(4)
+            .map(|w| w.iter().sum::<f64>() / 4.0)
+            .filter(|&e| energy_to_lufs(e) > ABSOLUTE_GATE_LUFS)
+            .collect();
+        if blocks.is_empty() {
+            return None;
+        }
+
+        let threshold = energy_to_lufs(mean(&blocks)) + RELATIVE_GATE_LU;
+        let gated: Vec<f64> = blocks
+            .into_iter()
+            .filter(|&e| energy_to_lufs(e) > threshold)
+            .collect();
+        (!gated.is_empty()).then(|| energy_to_lufs(mean(&gated)))
+    }
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+fn energy_to_lufs(energy: f64) -> f64 {
+    -0.691 + 10.0 * energy.log10()
+}
+
+/// Measures every listed file that has neither gain tags nor a stored loudness, in the background.
+#[tauri::command]
+pub fn analyze_loudness(state: State<AudioState>, paths: Vec<String>) -> Result<(), AppError> {
+    for path in paths {
+        let path = Path::new(&path);
+        if local_scanner::probe_replay_gain(path).is_none() {
+            lookup(&state.loudness, path, true);
+        }
+    }
+    Ok(())
+}
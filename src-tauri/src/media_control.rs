@@ -1,15 +1,20 @@
+use crate::audio::{self, AudioState};
 use crate::error::AppError;
+use crate::icy;
 use serde::Deserialize;
 use souvlaki::{MediaControls, MediaMetadata, MediaPlayback, PlatformConfig};
 use std::sync::{Arc, Mutex, Once};
-use tauri::Emitter;
-use tauri::State;
+use tauri::{AppHandle, Emitter, Manager, State};
 
 type Result<T> = std::result::Result<T, AppError>;
 
 pub struct MediaControlState {
     pub media_controls: Arc<Mutex<Option<MediaControls>>>,
     pub init_once: Once,
+    /// Metadata the webview set for the track, which stream titles are shown on top of.
+    pub metadata: Mutex<MediaMetadataInput>,
+    /// Last title a radio station announced, until the webview moves on to another track.
+    pub stream_title: Mutex<Option<String>>,
 }
 
 impl From<souvlaki::Error> for AppError {
@@ -44,12 +49,8 @@ pub async fn init_media_controls(
                 souvlaki::MediaControlEvent::Toggle => {
                     let _ = app.emit("media-control", "toggle");
                 }
-                souvlaki::MediaControlEvent::Next => {
-                    let _ = app.emit("media-control", "next");
-                }
-                souvlaki::MediaControlEvent::Previous => {
-                    let _ = app.emit("media-control", "previous");
-                }
+                souvlaki::MediaControlEvent::Next => skip(app.clone(), true),
+                souvlaki::MediaControlEvent::Previous => skip(app.clone(), false),
                 _ => {}
             }) {
                 *state.media_controls.lock().unwrap() = Some(controls);
@@ -60,11 +61,53 @@ pub async fn init_media_controls(
     Ok(())
 }
 
+/// Skips within the backend queue so media keys keep working while the webview is suspended
+/// in the tray. Falls back to the webview when the queue has nowhere to go.
+fn skip(app: AppHandle, forward: bool) {
+    tauri::async_runtime::spawn(async move {
+        let state = app.state::<AudioState>();
+        let result = if forward {
+            audio::skip_next(app.clone(), state).await
+        } else {
+            audio::skip_previous(app.clone(), state).await
+        };
+        if let Err(AppError::InvalidOperation(_)) = result {
+            let _ = app.emit("media-control", if forward { "next" } else { "previous" });
+        }
+    });
+}
+
 #[tauri::command]
 pub async fn update_media_metadata(
     state: State<'_, MediaControlState>,
     metadata: MediaMetadataInput,
 ) -> Result<()> {
+    {
+        let mut base = state.metadata.lock().unwrap();
+        if *base != metadata {
+            *state.stream_title.lock().unwrap() = None;
+            *base = metadata;
+        }
+    }
+    show_metadata(&state)
+}
+
+/// Shows the title an internet radio station announces, `Artist - Title` taken apart, over
+/// the station's own metadata.
+pub fn set_stream_title(state: &MediaControlState, title: &str) -> Result<()> {
+    *state.stream_title.lock().unwrap() = Some(title.to_string());
+    show_metadata(state)
+}
+
+fn show_metadata(state: &MediaControlState) -> Result<()> {
+    let mut metadata = state.metadata.lock().unwrap().clone();
+    if let Some(stream_title) = &*state.stream_title.lock().unwrap() {
+        let (artist, title) = icy::split_title(stream_title);
+        metadata.title = title.to_string();
+        if let Some(artist) = artist {
+            metadata.artist = artist.to_string();
+        }
+    }
     if let Some(controls) = &mut *state.media_controls.lock().unwrap() {
         let mut media_metadata = MediaMetadata::default();
         media_metadata.title = Some(&metadata.title);
@@ -93,7 +136,7 @@ pub async fn update_playback_status(
     Ok(())
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone, Default, PartialEq)]
 pub struct MediaMetadataInput {
     title: String,
     artist: String,
@@ -0,0 +1,368 @@
+use crate::analyzer::MeterTap;
+use crate::error::AppError;
+use rodio::cpal::traits::{DeviceTrait, HostTrait};
+use rodio::cpal::{self, Device};
+use rodio::mixer::{self, Mixer, MixerSource};
+use rodio::{OutputStream, OutputStreamBuilder, Source};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Runtime, State};
+use tokio::sync::oneshot;
+
+const SETTINGS_FILE: &str = "output.json";
+/// Mixer format when there is no default device to take it from.
+const FALLBACK_CHANNELS: u16 = 2;
+const FALLBACK_SAMPLE_RATE: u32 = 44100;
+/// Frames pulled from the internal mixer per lock.
+const RELAY_FRAMES: usize = 256;
+const DEVICE_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Serialize, Deserialize, Default)]
+struct OutputSettings {
+    device: Option<String>,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct OutputDevice {
+    pub name: String,
+    pub is_default: bool,
+    pub is_active: bool,
+}
+
+#[derive(Serialize, Clone)]
+pub struct OutputStatus {
+    /// Device chosen by the user, `None` when following the system default.
+    pub selected: Option<String>,
+    /// Device currently playing, `None` when no device could be opened.
+    pub active: Option<String>,
+}
+
+/// Output side of the internal mixer every sink is connected to.
+struct MixOutput {
+    source: Mutex<MixerSource<f32>>,
+    channels: u16,
+    sample_rate: u32,
+    generation: AtomicU64,
+    tap: Arc<MeterTap>,
+}
+
+/// Feeds the internal mixer into one device stream until a newer relay replaces it.
+struct Relay {
+    mix: Arc<MixOutput>,
+    generation: u64,
+    buffer: Vec<f32>,
+    position: usize,
+}
+
+impl Relay {
+    fn new(mix: Arc<MixOutput>, generation: u64) -> Self {
+        let chunk = RELAY_FRAMES * mix.channels as usize;
+        Relay {
+            mix,
+            generation,
+            buffer: Vec::with_capacity(chunk),
+            position: 0,
+        }
+    }
+}
+
+impl Iterator for Relay {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.position == self.buffer.len() {
+            let mut source = self.mix.source.lock().unwrap();
+            if self.mix.generation.load(Ordering::Acquire) != self.generation {
+                return None;
+            }
+            // The mixer ends whenever it has no inputs, keep the device fed with silence instead.
+            let chunk = RELAY_FRAMES * self.mix.channels as usize;
+            self.buffer.clear();
+            self.buffer
+                .extend((0..chunk).map(|_| source.next().unwrap_or(0.0)));
+            self.position = 0;
+            self.mix
+                .tap
+                .feed(&self.buffer, self.mix.channels, self.mix.sample_rate);
+        }
+
+        let sample = self.buffer[self.position];
+        self.position += 1;
+        Some(sample)
+    }
+}
+
+impl Source for Relay {
+    fn current_span_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.mix.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.mix.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+enum OutputRequest {
+    Select {
+        device: Option<String>,
+        reply: oneshot::Sender<Result<String, AppError>>,
+    },
+}
+
+/// Owns the device-independent mixer and talks to the thread holding the device stream.
+pub struct OutputState {
+    mixer: Arc<Mixer<f32>>,
+    mix: Arc<MixOutput>,
+    requests: Sender<OutputRequest>,
+    receiver: Mutex<Option<Receiver<OutputRequest>>>,
+    selected: Arc<Mutex<Option<String>>>,
+    active: Arc<Mutex<Option<String>>>,
+    file: Mutex<Option<PathBuf>>,
+}
+
+impl Default for OutputState {
+    fn default() -> Self {
+        let (channels, sample_rate) = mix_format();
+        let (mixer, source) = mixer::mixer(channels, sample_rate);
+        let (requests, receiver) = mpsc::channel();
+        OutputState {
+            mixer,
+            mix: Arc::new(MixOutput {
+                source: Mutex::new(source),
+                channels,
+                sample_rate,
+                generation: AtomicU64::new(0),
+                tap: Arc::new(MeterTap::default()),
+            }),
+            requests,
+            receiver: Mutex::new(Some(receiver)),
+            selected: Arc::new(Mutex::new(None)),
+            active: Arc::new(Mutex::new(None)),
+            file: Mutex::new(None),
+        }
+    }
+}
+
+impl OutputState {
+    /// The mixer sinks connect to; it outlives any device switch.
+    pub fn mixer(&self) -> Arc<Mixer<f32>> {
+        self.mixer.clone()
+    }
+
+    /// Copy of the mixed output for the spectrum and level meter.
+    pub fn meter_tap(&self) -> Arc<MeterTap> {
+        self.mix.tap.clone()
+    }
+
+    /// Loads the saved selection from `dir` and starts the device thread.
+    pub fn start<R: Runtime>(&self, app: AppHandle<R>, dir: Option<&Path>) {
+        if let Some(dir) = dir {
+            let file = dir.join(SETTINGS_FILE);
+            if let Ok(data) = fs::read(&file) {
+                let settings: OutputSettings = serde_json::from_slice(&data).unwrap_or_default();
+                *self.selected.lock().unwrap() = settings.device;
+            }
+            *self.file.lock().unwrap() = Some(file);
+        }
+
+        let Some(requests) = self.receiver.lock().unwrap().take() else {
+            return;
+        };
+        let mix = self.mix.clone();
+        let selected = self.selected.clone();
+        let active = self.active.clone();
+        thread::spawn(move || {
+            DeviceThread {
+                app,
+                mix,
+                stream: None,
+                selected,
+                active,
+            }
+            .run(requests)
+        });
+    }
+
+    fn save(&self) {
+        let Some(file) = self.file.lock().unwrap().clone() else {
+            return;
+        };
+        let settings = OutputSettings {
+            device: self.selected.lock().unwrap().clone(),
+        };
+        if let Some(dir) = file.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        if let Ok(data) = serde_json::to_vec(&settings) {
+            let _ = fs::write(file, data);
+        }
+    }
+}
+
+/// Holds the `OutputStream`, which has to stay on the thread that opened it.
+struct DeviceThread<R: Runtime> {
+    app: AppHandle<R>,
+    mix: Arc<MixOutput>,
+    stream: Option<OutputStream>,
+    selected: Arc<Mutex<Option<String>>>,
+    active: Arc<Mutex<Option<String>>>,
+}
+
+impl<R: Runtime> DeviceThread<R> {
+    fn run(mut self, requests: Receiver<OutputRequest>) {
+        self.check_devices();
+        loop {
+            match requests.recv_timeout(DEVICE_POLL_INTERVAL) {
+                Ok(OutputRequest::Select { device, reply }) => {
+                    let result = self.select(device);
+                    let _ = reply.send(result);
+                }
+                Err(RecvTimeoutError::Timeout) => self.check_devices(),
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+    }
+
+    fn select(&mut self, name: Option<String>) -> Result<String, AppError> {
+        let host = cpal::default_host();
+        let device = match &name {
+            Some(name) => find_device(&host, name).ok_or_else(|| {
+                AppError::OutputDeviceError(format!("Device not found: {}", name))
+            })?,
+            None => host.default_output_device().ok_or_else(|| {
+                AppError::OutputDeviceError("No default output device".to_string())
+            })?,
+        };
+        let active = self.open(device)?;
+        *self.selected.lock().unwrap() = name;
+        Ok(active)
+    }
+
+    /// Moves to the selected device when it is present and to the system default otherwise,
+    /// so a device that disappears falls back and one that comes back is picked up again.
+    fn check_devices(&mut self) {
+        let host = cpal::default_host();
+        let selected = self.selected.lock().unwrap().clone();
+        let target = selected
+            .and_then(|name| find_device(&host, &name))
+            .or_else(|| host.default_output_device());
+        let target_name = target.as_ref().and_then(|device| device.name().ok());
+        if target_name == *self.active.lock().unwrap() {
+            return;
+        }
+
+        match target {
+            Some(device) => {
+                if let Err(err) = self.open(device) {
+                    let _ = self.app.emit("output-device-error", err.to_string());
+                }
+            }
+            None => self.close(),
+        }
+    }
+
+    fn open(&mut self, device: Device) -> Result<String, AppError> {
+        let name = device.name().unwrap_or_default();
+        let stream = OutputStreamBuilder::from_device(device)
+            .and_then(|builder| builder.open_stream())
+            .map_err(|e| AppError::OutputDeviceError(e.to_string()))?;
+
+        let generation = self.mix.generation.fetch_add(1, Ordering::AcqRel) + 1;
+        stream.mixer().add(Relay::new(self.mix.clone(), generation));
+        self.stream = Some(stream);
+        self.set_active(Some(name.clone()));
+        Ok(name)
+    }
+
+    fn close(&mut self) {
+        self.mix.generation.fetch_add(1, Ordering::AcqRel);
+        self.stream = None;
+        self.set_active(None);
+    }
+
+    fn set_active(&self, name: Option<String>) {
+        *self.active.lock().unwrap() = name.clone();
+        let _ = self.app.emit("output-device-changed", name);
+    }
+}
+
+/// The default device's own format, so with the usual setup tracks are resampled once on their
+/// way into the mixer and keep every channel the device has. Other devices convert from it.
+fn mix_format() -> (u16, u32) {
+    cpal::default_host()
+        .default_output_device()
+        .and_then(|device| device.default_output_config().ok())
+        .map_or((FALLBACK_CHANNELS, FALLBACK_SAMPLE_RATE), |config| {
+            (config.channels(), config.sample_rate().0)
+        })
+}
+
+fn find_device(host: &cpal::Host, name: &str) -> Option<Device> {
+    host.output_devices()
+        .ok()?
+        .find(|device| device.name().is_ok_and(|n| n == name))
+}
+
+#[tauri::command]
+pub fn list_output_devices(state: State<OutputState>) -> Result<Vec<OutputDevice>, AppError> {
+    let host = cpal::default_host();
+    let default = host.default_output_device().and_then(|d| d.name().ok());
+    let active = state.active.lock().unwrap().clone();
+    let devices = host
+        .output_devices()
+        .map_err(|e| AppError::OutputDeviceError(e.to_string()))?;
+
+    Ok(devices
+        .filter_map(|device| device.name().ok())
+        .map(|name| OutputDevice {
+            is_default: default.as_ref() == Some(&name),
+            is_active: active.as_ref() == Some(&name),
+            name,
+        })
+        .collect())
+}
+
+/// Switches playback to the named device, or back to the system default for `None`.
+/// Playing tracks carry on from the same position on the new device.
+#[tauri::command]
+pub async fn set_output_device(
+    state: State<'_, OutputState>,
+    name: Option<String>,
+) -> Result<String, AppError> {
+    let (reply, response) = oneshot::channel();
+    state
+        .requests
+        .send(OutputRequest::Select {
+            device: name,
+            reply,
+        })
+        .map_err(|_| AppError::OutputDeviceError("Output thread is not running".to_string()))?;
+    let active = response
+        .await
+        .map_err(|_| AppError::OutputDeviceError("Output thread is not running".to_string()))??;
+    state.save();
+    Ok(active)
+}
+
+#[tauri::command]
+pub fn get_output_device(state: State<OutputState>) -> Result<OutputStatus, AppError> {
+    Ok(OutputStatus {
+        selected: state.selected.lock().unwrap().clone(),
+        active: state.active.lock().unwrap().clone(),
+    })
+}
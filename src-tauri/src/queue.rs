@@ -0,0 +1,469 @@
+use crate::http::RequestOptions;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum TrackSource {
+    Local { path: String },
+    Url {
+        url: String,
+        /// Headers and auth the host needs to serve the track.
+        #[serde(default, skip_serializing_if = "RequestOptions::is_empty")]
+        request: RequestOptions,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct QueueItem {
+    pub id: String,
+    pub source: TrackSource,
+    /// Used by smart shuffle to keep tracks of the same artist or album apart.
+    #[serde(default)]
+    pub artist: Option<String>,
+    #[serde(default)]
+    pub album: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum RepeatMode {
+    #[default]
+    Off,
+    One,
+    All,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum ShuffleMode {
+    #[default]
+    Off,
+    Shuffle,
+    /// Shuffle that avoids back-to-back tracks by the same artist or from the same album.
+    Smart,
+}
+
+#[derive(Serialize, Clone, Default)]
+pub struct PlayQueue {
+    items: Vec<QueueItem>,
+    current: Option<usize>,
+    repeat: RepeatMode,
+    shuffle: ShuffleMode,
+    /// Queue indices in the order they play. Up to a pass of played entries stays in front so
+    /// going back retraces what was actually heard.
+    #[serde(skip)]
+    order: Vec<usize>,
+    /// Position of the current entry in `order`.
+    #[serde(skip)]
+    position: Option<usize>,
+    /// Start of a repeat pass appended to `order` that has not been entered yet.
+    #[serde(skip)]
+    next_pass: Option<usize>,
+}
+
+impl PlayQueue {
+    pub fn replace(&mut self, items: Vec<QueueItem>) {
+        self.items = items;
+        self.current = None;
+        self.position = None;
+        self.plan_upcoming(None);
+    }
+
+    pub fn extend(&mut self, items: Vec<QueueItem>) {
+        let added = self.items.len()..self.items.len() + items.len();
+        self.items.extend(items);
+
+        if self.shuffle == ShuffleMode::Off {
+            self.plan_upcoming(None);
+            return;
+        }
+        // Never in between the current entry and the one that may already be preloaded, nor in
+        // a pending repeat pass that leaving repeat-all would drop
+        let mut rng = Rng::new();
+        for index in added {
+            let end = self.next_pass.unwrap_or(self.order.len());
+            let earliest = self.position.map_or(0, |p| p + 2).min(end);
+            let at = earliest + rng.below(end - earliest + 1);
+            self.order.insert(at, index);
+            if let Some(start) = &mut self.next_pass {
+                *start += 1;
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.items.clear();
+        self.current = None;
+        self.order.clear();
+        self.position = None;
+        self.next_pass = None;
+    }
+
+    pub fn get(&self, index: usize) -> Option<&QueueItem> {
+        self.items.get(index)
+    }
+
+    pub fn items(&self) -> &[QueueItem] {
+        &self.items
+    }
+
+    pub fn current_index(&self) -> Option<usize> {
+        self.current
+    }
+
+    pub fn set_current(&mut self, index: usize) {
+        let position = match self.find(index) {
+            Some(position) => position,
+            None => {
+                self.plan_upcoming(Some(index));
+                self.position.map_or(0, |p| p + 1)
+            }
+        };
+        if self.next_pass.is_some_and(|start| position >= start) {
+            self.next_pass = None;
+        }
+        self.position = Some(position);
+        self.current = Some(index);
+    }
+
+    /// Makes `index` the next entry to play, as when the user picks a track explicitly.
+    pub fn jump_to(&mut self, index: usize) {
+        self.plan_upcoming(Some(index));
+    }
+
+    pub fn repeat(&self) -> RepeatMode {
+        self.repeat
+    }
+
+    pub fn set_repeat(&mut self, repeat: RepeatMode) {
+        self.repeat = repeat;
+        if repeat != RepeatMode::All {
+            if let Some(start) = self.next_pass.take() {
+                self.order.truncate(start);
+            }
+        }
+    }
+
+    pub fn shuffle(&self) -> ShuffleMode {
+        self.shuffle
+    }
+
+    pub fn set_shuffle(&mut self, shuffle: ShuffleMode) {
+        if self.shuffle != shuffle {
+            self.shuffle = shuffle;
+            self.plan_upcoming(None);
+        }
+    }
+
+    /// Returns the index that should play after `index` once it ends, if any.
+    pub fn next_index(&mut self, index: usize) -> Option<usize> {
+        if self.repeat == RepeatMode::One && index < self.items.len() {
+            return Some(index);
+        }
+        self.skip_index(index)
+    }
+
+    /// Returns the index a manual skip from `index` goes to, which leaves repeat-one behind.
+    pub fn skip_index(&mut self, index: usize) -> Option<usize> {
+        let Some(position) = self.find(index) else {
+            if self.shuffle == ShuffleMode::Off {
+                let next = index + 1;
+                return (next < self.items.len()).then_some(next);
+            }
+            // Lost in the shuffled order, carry on with a freshly planned one
+            self.plan_upcoming(None);
+            let upcoming = self.order.get(self.position.map_or(0, |p| p + 1)..)?;
+            return upcoming.iter().copied().find(|&next| next != index);
+        };
+
+        if position + 1 == self.order.len() && self.repeat == RepeatMode::All {
+            // Rebuilt from the last pass of history and the new pass, so long sessions do not
+            // grow it without end
+            let earliest = self.position.map_or(position, |p| p.min(position));
+            let start = earliest.saturating_sub(self.items.len());
+            let pass = self.arrange((0..self.items.len()).collect(), Some(index));
+            let mut order = self.order.split_off(start);
+            self.next_pass = Some(order.len());
+            order.extend(pass);
+            self.order = order;
+            self.position = self.position.map(|p| p - start);
+            return self.order.get(position - start + 1).copied();
+        }
+        self.order.get(position + 1).copied()
+    }
+
+    /// Returns the index that played before `index`, if any.
+    pub fn previous_index(&self, index: usize) -> Option<usize> {
+        if self.shuffle == ShuffleMode::Off {
+            return match index.checked_sub(1) {
+                None if self.repeat == RepeatMode::All => self.items.len().checked_sub(1),
+                previous => previous,
+            };
+        }
+        let position = self.find(index)?;
+        position.checked_sub(1).map(|p| self.order[p])
+    }
+
+    /// Locates `index` in `order`, preferring the entries around the current position.
+    fn find(&self, index: usize) -> Option<usize> {
+        let position = self.position?;
+        let near = [Some(position), Some(position + 1), position.checked_sub(1)];
+        near.into_iter()
+            .flatten()
+            .find(|&p| self.order.get(p) == Some(&index))
+            .or_else(|| {
+                let ahead = self.order.get(position..)?;
+                ahead.iter().position(|&i| i == index).map(|p| position + p)
+            })
+            .or_else(|| self.order[..position].iter().rposition(|&i| i == index))
+    }
+
+    /// Replaces everything after the current position with the rest of a fresh pass, starting
+    /// with `first` when given.
+    fn plan_upcoming(&mut self, first: Option<usize>) {
+        self.order.truncate(self.position.map_or(0, |p| p + 1));
+        self.next_pass = None;
+
+        let anchor = first.or(self.current);
+        if let Some(first) = first {
+            self.order.push(first);
+        }
+        let count = self.items.len();
+        let rest = match (self.shuffle, anchor) {
+            (ShuffleMode::Off, Some(anchor)) => (anchor + 1..count).collect(),
+            (ShuffleMode::Off, None) => (0..count).collect(),
+            (_, anchor) => {
+                let others = (0..count).filter(|&i| Some(i) != anchor).collect();
+                self.arrange(others, anchor)
+            }
+        };
+        self.order.extend(rest);
+    }
+
+    /// Puts `indices` in play order for the current shuffle mode, `previous` being the entry
+    /// that plays right before them.
+    fn arrange(&self, mut indices: Vec<usize>, previous: Option<usize>) -> Vec<usize> {
+        if self.shuffle == ShuffleMode::Off {
+            indices.sort_unstable();
+            return indices;
+        }
+
+        let mut rng = Rng::new();
+        for i in (1..indices.len()).rev() {
+            indices.swap(i, rng.below(i + 1));
+        }
+
+        // Wherever two neighbours clash, pull the nearest later entry that does not clash
+        for i in 0..indices.len() {
+            let Some(before) = i.checked_sub(1).map(|p| indices[p]).or(previous) else {
+                continue;
+            };
+            if !self.clashes(before, indices[i]) {
+                continue;
+            }
+            if let Some(j) = (i + 1..indices.len()).find(|&j| !self.clashes(before, indices[j])) {
+                indices.swap(i, j);
+            }
+        }
+        indices
+    }
+
+    fn clashes(&self, a: usize, b: usize) -> bool {
+        if a == b {
+            return true;
+        }
+        if self.shuffle != ShuffleMode::Smart {
+            return false;
+        }
+        let (Some(a), Some(b)) = (self.items.get(a), self.items.get(b)) else {
+            return false;
+        };
+        let same = |x: &Option<String>, y: &Option<String>| match (x, y) {
+            (Some(x), Some(y)) => !x.is_empty() && x.eq_ignore_ascii_case(y),
+            _ => false,
+        };
+        same(&a.artist, &b.artist) || same(&a.album, &b.album)
+    }
+}
+
+/// Small xorshift generator, shuffling a play queue needs nothing stronger.
+struct Rng(u64);
+
+impl Rng {
+    fn new() -> Self {
+        Rng(RandomState::new().build_hasher().finish() | 1)
+    }
+
+    fn below(&mut self, bound: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % bound as u64) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(id: usize, artist: &str, album: &str) -> QueueItem {
+        QueueItem {
+            id: id.to_string(),
+            source: TrackSource::Local {
+                path: format!("{}.flac", id),
+            },
+            artist: Some(artist.to_string()),
+            album: Some(album.to_string()),
+        }
+    }
+
+    fn items(count: usize) -> Vec<QueueItem> {
+        (0..count).map(|i| item(i, "", "")).collect()
+    }
+
+    fn queue(count: usize, shuffle: ShuffleMode, repeat: RepeatMode) -> PlayQueue {
+        let mut queue = PlayQueue::default();
+        queue.set_shuffle(shuffle);
+        queue.set_repeat(repeat);
+        queue.replace(items(count));
+        queue.jump_to(0);
+        queue.set_current(0);
+        queue
+    }
+
+    /// Lets `count` tracks end one after another, returning what played.
+    fn play(queue: &mut PlayQueue, count: usize) -> Vec<usize> {
+        let mut played = Vec::new();
+        for _ in 0..count {
+            let next = queue.next_index(queue.current_index().unwrap()).unwrap();
+            queue.set_current(next);
+            played.push(next);
+        }
+        played
+    }
+
+    fn sorted(mut indices: Vec<usize>) -> Vec<usize> {
+        indices.sort_unstable();
+        indices
+    }
+
+    #[test]
+    fn repeat_all_shuffle_plays_every_track_each_pass() {
+        let mut queue = queue(5, ShuffleMode::Shuffle, RepeatMode::All);
+        let mut played = vec![0];
+        played.extend(play(&mut queue, 49));
+
+        for pass in played.chunks(5) {
+            assert_eq!(sorted(pass.to_vec()), vec![0, 1, 2, 3, 4]);
+        }
+        for pair in played.windows(2) {
+            assert_ne!(pair[0], pair[1]);
+        }
+        assert!(queue.order.len() <= 3 * 5);
+    }
+
+    #[test]
+    fn previous_retraces_across_passes() {
+        let mut queue = queue(4, ShuffleMode::Shuffle, RepeatMode::All);
+        let mut played = vec![0];
+        played.extend(play(&mut queue, 9));
+
+        let current = *played.last().unwrap();
+        assert_eq!(queue.previous_index(current), Some(played[played.len() - 2]));
+    }
+
+    #[test]
+    fn extend_mid_pass_keeps_pending_pass() {
+        let mut queue = queue(4, ShuffleMode::Shuffle, RepeatMode::All);
+        play(&mut queue, 3);
+        // Planning what follows the last entry appends the next pass
+        let last = queue.current_index().unwrap();
+        let first = queue.skip_index(last).unwrap();
+        let start = queue.next_pass.unwrap();
+
+        queue.extend(items(2));
+        let start_after = queue.next_pass.unwrap();
+        assert_eq!(start_after, start + 2);
+        assert_eq!(queue.order[start_after], first);
+        assert_eq!(sorted(queue.order[start_after..].to_vec()), vec![0, 1, 2, 3]);
+
+        // The added tracks play before the new pass begins
+        let played = play(&mut queue, 2);
+        assert_eq!(sorted(played), vec![4, 5]);
+        assert_eq!(play(&mut queue, 1), vec![first]);
+    }
+
+    #[test]
+    fn extend_in_order_appends() {
+        let mut queue = queue(3, ShuffleMode::Off, RepeatMode::Off);
+        queue.extend(items(2));
+        assert_eq!(play(&mut queue, 4), vec![1, 2, 3, 4]);
+        assert_eq!(queue.next_index(4), None);
+    }
+
+    #[test]
+    fn leaving_repeat_all_drops_only_the_pending_pass() {
+        let mut queue = queue(4, ShuffleMode::Shuffle, RepeatMode::All);
+        play(&mut queue, 1);
+        let position = queue.position.unwrap();
+        queue.extend(items(1));
+        let upcoming = queue.order[position + 1..].to_vec();
+        let last = *upcoming.last().unwrap();
+        queue.skip_index(last);
+        assert!(queue.next_pass.is_some());
+
+        queue.set_repeat(RepeatMode::Off);
+        assert_eq!(queue.next_pass, None);
+        assert_eq!(queue.order[position + 1..], upcoming[..]);
+        assert!(upcoming.contains(&4));
+        assert_eq!(queue.skip_index(last), None);
+    }
+
+    #[test]
+    fn repeat_one_sticks_until_skipped() {
+        let mut queue = queue(3, ShuffleMode::Off, RepeatMode::One);
+        assert_eq!(queue.next_index(0), Some(0));
+        assert_eq!(queue.skip_index(0), Some(1));
+    }
+
+    #[test]
+    fn previous_in_order_wraps_with_repeat_all() {
+        let repeating = queue(3, ShuffleMode::Off, RepeatMode::All);
+        assert_eq!(repeating.previous_index(0), Some(2));
+        let once = queue(3, ShuffleMode::Off, RepeatMode::Off);
+        assert_eq!(once.previous_index(0), None);
+    }
+
+    #[test]
+    fn smart_shuffle_keeps_artists_and_albums_apart() {
+        let mut queue = PlayQueue::default();
+        queue.set_shuffle(ShuffleMode::Smart);
+        queue.replace(vec![
+            item(0, "A", "x"),
+            item(1, "a", "y"),
+            item(2, "B", "z"),
+            item(3, "C", "Z"),
+        ]);
+        assert!(queue.clashes(0, 1));
+        assert!(queue.clashes(2, 3));
+        assert!(!queue.clashes(0, 2));
+
+        for _ in 0..50 {
+            queue.replace(queue.items.clone());
+            for pair in queue.order.windows(2) {
+                assert!(!queue.clashes(pair[0], pair[1]), "{:?}", queue.order);
+            }
+        }
+    }
+
+    #[test]
+    fn lost_shuffled_skip_plans_a_new_order() {
+        let mut queue = queue(4, ShuffleMode::Shuffle, RepeatMode::Off);
+        queue.order.clear();
+        let next = queue.skip_index(2).unwrap();
+        assert_ne!(next, 2);
+        assert!(queue.order.contains(&next));
+    }
+}
@@ -0,0 +1,155 @@
+use crate::audio::{self, AudioState, StartOptions};
+use crate::queue::{QueueItem, RepeatMode, ShuffleMode};
+use crate::stretch::SpeedMode;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager, Runtime};
+
+const SESSION_FILE: &str = "session.json";
+const SAVE_INTERVAL: Duration = Duration::from_secs(10);
+
+fn default_volume() -> f32 {
+    1.0
+}
+
+fn default_speed() -> f32 {
+    1.0
+}
+
+/// What is needed to pick playback up where it was left.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Session {
+    pub items: Vec<QueueItem>,
+    pub current: Option<usize>,
+    /// Seconds into the current track.
+    pub position: f32,
+    #[serde(default = "default_volume")]
+    pub volume: f32,
+    #[serde(default = "default_speed")]
+    pub speed: f32,
+    #[serde(default)]
+    pub speed_mode: SpeedMode,
+    #[serde(default)]
+    pub repeat: RepeatMode,
+    #[serde(default)]
+    pub shuffle: ShuffleMode,
+}
+
+impl Session {
+    fn capture(state: &AudioState) -> Self {
+        let (items, current, repeat, shuffle) = {
+            let queue = state.queue.lock().unwrap();
+            (
+                queue.items().to_vec(),
+                queue.current_index(),
+                queue.repeat(),
+                queue.shuffle(),
+            )
+        };
+        let volume = state.sink.lock().unwrap().as_ref().map_or(1.0, |s| s.volume());
+        // A seek still waiting for the download is where playback will continue from
+        let pending = *state.seek_target.lock().unwrap();
+        let position = pending
+            .or_else(|| audio::current_track_position(state))
+            .unwrap_or_default();
+
+        Session {
+            items,
+            current,
+            position: position.as_secs_f32(),
+            volume,
+            speed: state.tempo.speed(),
+            speed_mode: state.tempo.mode(),
+            repeat,
+            shuffle,
+        }
+    }
+}
+
+/// Snapshots the session to the app data directory and brings it back on launch.
+#[derive(Default)]
+pub struct SessionState {
+    file: Mutex<Option<PathBuf>>,
+    /// Last snapshot written, to skip rewriting an unchanged file.
+    saved: Mutex<Vec<u8>>,
+}
+
+impl SessionState {
+    /// Restores the session saved in `dir`, paused, and starts saving it periodically.
+    pub fn start<R: Runtime>(&self, app: AppHandle<R>, dir: Option<&Path>) {
+        let Some(dir) = dir else {
+            return;
+        };
+        let file = dir.join(SESSION_FILE);
+        let session = fs::read(&file).ok().and_then(|data| {
+            *self.saved.lock().unwrap() = data.clone();
+            serde_json::from_slice::<Session>(&data).ok()
+        });
+        *self.file.lock().unwrap() = Some(file);
+
+        tauri::async_runtime::spawn(async move {
+            if let Some(session) = session {
+                restore(&app, session).await;
+            }
+            loop {
+                tokio::time::sleep(SAVE_INTERVAL).await;
+                save(&app);
+            }
+        });
+    }
+}
+
+async fn restore<R: Runtime>(app: &AppHandle<R>, session: Session) {
+    let state = app.state::<AudioState>();
+    let sink_speed = state.tempo.set(session.speed, session.speed_mode);
+    {
+        let mut queue = state.queue.lock().unwrap();
+        queue.set_repeat(session.repeat);
+        queue.set_shuffle(session.shuffle);
+    }
+
+    let Some(current) = session.current.filter(|&i| i < session.items.len()) else {
+        state.queue.lock().unwrap().replace(session.items);
+        return;
+    };
+    let options = StartOptions {
+        volume: Some(session.volume),
+        sink_speed: Some(sink_speed),
+        position: Some(Duration::from_secs_f32(session.position.max(0.0))),
+        paused: true,
+    };
+    match audio::start_queue(app, &state, session.items.clone(), current, options).await {
+        Ok(()) => {
+            let _ = app.emit("session_restored", session);
+        }
+        Err(e) => {
+            let _ = app.emit("session_restore_failed", e.to_string());
+        }
+    }
+}
+
+/// Writes the session out if it changed since the last time.
+pub fn save<R: Runtime>(app: &AppHandle<R>) {
+    let session_state = app.state::<SessionState>();
+    let Some(file) = session_state.file.lock().unwrap().clone() else {
+        return;
+    };
+    let Ok(data) = serde_json::to_vec(&Session::capture(&app.state::<AudioState>())) else {
+        return;
+    };
+
+    let mut saved = session_state.saved.lock().unwrap();
+    if *saved == data {
+        return;
+    }
+    if let Some(dir) = file.parent() {
+        let _ = fs::create_dir_all(dir);
+    }
+    if fs::write(&file, &data).is_ok() {
+        *saved = data;
+    }
+}
@@ -0,0 +1,229 @@
+use crate::audio::{self, AudioState};
+use crate::error::AppError;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, Manager, Runtime, State};
+
+const TICK: Duration = Duration::from_millis(100);
+/// Longest timer, and fade, that can be set.
+const MAX_MINUTES: f32 = 24.0 * 60.0;
+
+fn default_fade() -> f32 {
+    30.0
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum SleepTimerMode {
+    Minutes { minutes: f32 },
+    EndOfTrack,
+    /// Stops at the end of the `count`th track, the current one counting as the first.
+    Tracks { count: u32 },
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SleepTimerSettings {
+    pub mode: SleepTimerMode,
+    /// Seconds the volume takes to fade out before playback pauses.
+    #[serde(default = "default_fade")]
+    pub fade: f32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SleepTimerStatus {
+    /// `None` while no timer is set.
+    pub mode: Option<SleepTimerMode>,
+    /// Whole seconds until playback pauses, when known.
+    pub remaining: Option<f32>,
+    /// Tracks left including the current one, for the track based modes.
+    pub tracks_left: Option<u32>,
+    pub fading: bool,
+}
+
+/// Text of the tray menu entry, which cancels the timer when clicked.
+pub fn tray_label(status: &SleepTimerStatus) -> String {
+    if status.mode.is_none() {
+        return "Sleep timer: off".to_string();
+    }
+    match (status.remaining, status.tracks_left) {
+        (Some(remaining), _) if remaining >= 60.0 => {
+            format!("Cancel sleep timer ({} min left)", (remaining / 60.0).ceil())
+        }
+        (Some(remaining), _) => format!("Cancel sleep timer ({} s left)", remaining),
+        (None, Some(1)) => "Cancel sleep timer (after this track)".to_string(),
+        (None, Some(tracks)) => format!("Cancel sleep timer ({} tracks left)", tracks),
+        (None, None) => "Cancel sleep timer".to_string(),
+    }
+}
+
+#[derive(Default)]
+pub struct SleepTimerState {
+    /// Bumped whenever a timer is set or cancelled, running timers exit when it changed.
+    generation: AtomicU64,
+    status: Mutex<SleepTimerStatus>,
+    /// Volume from before the fade started, restored when the timer ends or is cancelled.
+    faded_from: Mutex<Option<f32>>,
+}
+
+impl SleepTimerState {
+    /// Ends the running timer and puts the volume back where the fade started.
+    fn stop(&self, audio: &AudioState) {
+        let faded_from = {
+            let mut faded_from = self.faded_from.lock().unwrap();
+            self.generation.fetch_add(1, Ordering::AcqRel);
+            faded_from.take()
+        };
+        if let Some(volume) = faded_from {
+            if let Some(sink) = &*audio.sink.lock().unwrap() {
+                sink.set_volume(volume);
+            }
+        }
+    }
+
+    /// Emits the status when it changed. A timer passes its generation so a tick racing a
+    /// cancel cannot bring the old status back.
+    fn publish<R: Runtime>(
+        &self,
+        app: &AppHandle<R>,
+        status: SleepTimerStatus,
+        generation: Option<u64>,
+    ) {
+        let mut current = self.status.lock().unwrap();
+        if generation.is_some_and(|g| g != self.generation.load(Ordering::Acquire)) {
+            return;
+        }
+        if *current != status {
+            *current = status.clone();
+            let _ = app.emit("sleep_timer_event", status);
+        }
+    }
+}
+
+/// Stops the running timer, if any, restoring the volume it faded.
+pub fn cancel<R: Runtime>(app: &AppHandle<R>) {
+    let timer = app.state::<SleepTimerState>();
+    timer.stop(&app.state::<AudioState>());
+    timer.publish(app, SleepTimerStatus::default(), None);
+}
+
+fn spawn<R: Runtime>(app: AppHandle<R>, settings: SleepTimerSettings, generation: u64) {
+    tauri::async_runtime::spawn(async move {
+        let fade = Duration::from_secs_f32(settings.fade.max(0.0));
+        let deadline = match settings.mode {
+            SleepTimerMode::Minutes { minutes } => {
+                Some(Instant::now() + Duration::from_secs_f32(minutes.max(0.0) * 60.0))
+            }
+            _ => None,
+        };
+        let mut tracks_left = match settings.mode {
+            SleepTimerMode::Minutes { .. } => None,
+            SleepTimerMode::EndOfTrack => Some(1),
+            SleepTimerMode::Tracks { count } => Some(count.max(1)),
+        };
+        let mut track = None;
+
+        loop {
+            let timer = app.state::<SleepTimerState>();
+            if timer.generation.load(Ordering::Acquire) != generation {
+                return;
+            }
+            let audio = app.state::<AudioState>();
+            let current = audio::current_track_remaining(&audio);
+
+            if let Some(left) = tracks_left.as_mut() {
+                let token = current.map(|(token, _)| token);
+                match (track, token) {
+                    (Some(previous), Some(token)) if previous != token => *left -= 1,
+                    (Some(_), None) => *left = 0,
+                    _ => {}
+                }
+                track = token;
+            }
+
+            let remaining = match (deadline, tracks_left) {
+                (Some(deadline), _) => Some(deadline.saturating_duration_since(Instant::now())),
+                (None, Some(0)) => Some(Duration::ZERO),
+                (None, Some(1)) => current.and_then(|(_, remaining)| remaining),
+                _ => None,
+            };
+
+            if remaining == Some(Duration::ZERO) {
+                // The sleep fade already silenced the output, a transport fade would pause
+                // only after the volume was restored
+                audio::pause_now(&audio);
+                timer.stop(&audio);
+                timer.publish(&app, SleepTimerStatus::default(), None);
+                // Let the webview catch up with the pause when it is awake
+                let _ = app.emit("media-control", "pause");
+                return;
+            }
+
+            let fading = remaining.is_some_and(|remaining| remaining <= fade);
+            if let (true, Some(remaining)) = (fading, remaining) {
+                let sink = audio.sink.lock().unwrap();
+                let mut faded_from = timer.faded_from.lock().unwrap();
+                // A cancel in between must not be undone by a late volume change
+                if let (Some(sink), true) = (
+                    &*sink,
+                    timer.generation.load(Ordering::Acquire) == generation,
+                ) {
+                    let from = *faded_from.get_or_insert_with(|| sink.volume());
+                    let gain = remaining.as_secs_f32() / fade.as_secs_f32();
+                    sink.set_volume(from * gain * gain);
+                }
+            }
+
+            timer.publish(
+                &app,
+                SleepTimerStatus {
+                    mode: Some(settings.mode),
+                    remaining: remaining.map(|r| r.as_secs_f32().ceil()),
+                    tracks_left,
+                    fading,
+                },
+                Some(generation),
+            );
+            tokio::time::sleep(TICK).await;
+        }
+    });
+}
+
+/// Pauses playback after a while or a number of tracks, fading the volume out first.
+#[tauri::command]
+pub fn set_sleep_timer<R: Runtime>(
+    app: AppHandle<R>,
+    settings: SleepTimerSettings,
+) -> Result<(), AppError> {
+    // Out of range floats would make `Duration::from_secs_f32` panic in the timer task
+    let valid = match settings.mode {
+        SleepTimerMode::Minutes { minutes } => minutes > 0.0 && minutes <= MAX_MINUTES,
+        SleepTimerMode::EndOfTrack => true,
+        SleepTimerMode::Tracks { count } => count > 0,
+    };
+    if !valid || !(0.0..=MAX_MINUTES * 60.0).contains(&settings.fade) {
+        return Err(AppError::InvalidOperation("Invalid sleep timer".to_string()));
+    }
+
+    cancel(&app);
+    let generation = app
+        .state::<SleepTimerState>()
+        .generation
+        .load(Ordering::Acquire);
+    spawn(app, settings, generation);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn cancel_sleep_timer<R: Runtime>(app: AppHandle<R>) -> Result<(), AppError> {
+    cancel(&app);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_sleep_timer(state: State<SleepTimerState>) -> Result<SleepTimerStatus, AppError> {
+    Ok(state.status.lock().unwrap().clone())
+}
@@ -0,0 +1,756 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::{Decoder as CodecDecoder, DecoderOptions, CODEC_TYPE_NULL};
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::{FormatOptions, FormatReader, SeekMode, SeekTo};
+use symphonia::core::io::{MediaSource, MediaSourceStream};
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+use symphonia::core::units::{Time, TimeBase};
+use tokio::sync::Notify;
+
+/// What a `StreamBuffer` does with bytes over its memory limit.
+#[derive(Clone, Copy, Debug)]
+pub enum Overflow {
+    /// Keeps every byte in memory.
+    Unbounded,
+    /// Moves bytes to a temporary file, those the reader has passed first, then those furthest
+    /// ahead of it. For streams that can seek back.
+    Spill(u64),
+    /// Drops the oldest bytes, for endless streams that only play forward.
+    Discard(u64),
+}
+
+/// Names spill files apart within this process.
+static SPILL_FILES: AtomicU64 = AtomicU64::new(0);
+
+/// Bytes kept on disk at their stream offsets: moved out of memory into a sparse temporary
+/// file, or read in place from the disk cache.
+struct SpillFile {
+    file: File,
+    /// Set for temporary files, which are removed once the stream is gone.
+    path: Option<PathBuf>,
+    /// Spilled ranges, start to end.
+    ranges: BTreeMap<u64, u64>,
+}
+
+impl SpillFile {
+    fn create() -> io::Result<Self> {
+        let path = std::env::temp_dir().join(format!(
+            "cicadas-stream-{}-{}.tmp",
+            std::process::id(),
+            SPILL_FILES.fetch_add(1, Ordering::Relaxed)
+        ));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        Ok(SpillFile {
+            file,
+            path: Some(path),
+            ranges: BTreeMap::new(),
+        })
+    }
+
+    /// Reads the `[start, end)` ranges of a cache file, which stays where it is.
+    fn open_cached(path: &Path, ranges: &[(u64, u64)]) -> io::Result<Self> {
+        Ok(SpillFile {
+            file: File::open(path)?,
+            path: None,
+            ranges: ranges.iter().copied().collect(),
+        })
+    }
+
+    fn write(&mut self, offset: u64, data: &[u8]) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.write_all(data)?;
+
+        let mut start = offset;
+        let mut end = offset + data.len() as u64;
+        // Merge with the ranges the new one touches
+        let touching: Vec<u64> = self
+            .ranges
+            .range(..=end)
+            .filter(|(_, &range_end)| range_end >= offset)
+            .map(|(&range_start, _)| range_start)
+            .collect();
+        for range_start in touching {
+            let range_end = self.ranges.remove(&range_start).unwrap();
+            start = start.min(range_start);
+            end = end.max(range_end);
+        }
+        self.ranges.insert(start, end);
+        Ok(())
+    }
+
+    /// End of the spilled range holding `position`.
+    fn end_at(&self, position: u64) -> Option<u64> {
+        self.ranges
+            .range(..=position)
+            .next_back()
+            .map(|(_, &end)| end)
+            .filter(|&end| end > position)
+    }
+
+    fn read_at(&mut self, position: u64, buf: &mut [u8]) -> Option<usize> {
+        let end = self.end_at(position)?;
+        let to_read = buf.len().min((end - position) as usize);
+        self.file.seek(SeekFrom::Start(position)).ok()?;
+        self.file.read_exact(&mut buf[..to_read]).ok()?;
+        Some(to_read)
+    }
+
+    fn len(&self) -> u64 {
+        self.ranges.iter().map(|(start, end)| end - start).sum()
+    }
+}
+
+impl Drop for SpillFile {
+    fn drop(&mut self) {
+        if let Some(path) = &self.path {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// Downloaded bytes, as sorted, non-touching ranges in memory and whatever was spilled to disk.
+struct Storage {
+    memory: BTreeMap<u64, Vec<u8>>,
+    memory_bytes: u64,
+    overflow: Overflow,
+    spill: Option<SpillFile>,
+    /// Set when no spill file could be created, everything then stays in memory.
+    spill_failed: bool,
+    /// Why spilling stopped working, until the download task reports it.
+    spill_error: Option<String>,
+    /// What the disk cache already held when the stream was opened.
+    cached: Option<SpillFile>,
+}
+
+impl Storage {
+    fn write(&mut self, offset: u64, bytes: &[u8]) {
+        let memory = &mut self.memory;
+        let previous = memory
+            .range(..=offset)
+            .next_back()
+            .filter(|(&start, data)| start + data.len() as u64 >= offset)
+            .map(|(&start, _)| start);
+        let (start, mut data) = match previous {
+            Some(start) => (start, memory.remove(&start).unwrap()),
+            None => (offset, Vec::new()),
+        };
+        let mut replaced = data.len() as u64;
+
+        let end = start + data.len() as u64;
+        if offset + bytes.len() as u64 > end {
+            data.extend_from_slice(&bytes[(end - offset) as usize..]);
+        }
+
+        // Absorb following ranges the new bytes now reach
+        loop {
+            let end = start + data.len() as u64;
+            let Some((&next, _)) = memory.range(start..).next() else {
+                break;
+            };
+            if next > end {
+                break;
+            }
+            let next_data = memory.remove(&next).unwrap();
+            replaced += next_data.len() as u64;
+            if next + next_data.len() as u64 > end {
+                data.extend_from_slice(&next_data[(end - next) as usize..]);
+            }
+        }
+        self.memory_bytes = self.memory_bytes - replaced + data.len() as u64;
+        memory.insert(start, data);
+    }
+
+    /// Removes the bytes in `from..to` from memory, returning them in pieces.
+    fn cut(&mut self, from: u64, to: u64) -> Vec<(u64, Vec<u8>)> {
+        let starts: Vec<u64> = self
+            .memory
+            .range(..to)
+            .filter(|(&start, data)| start + data.len() as u64 > from)
+            .map(|(&start, _)| start)
+            .collect();
+        let mut pieces = Vec::new();
+        for start in starts {
+            let mut data = self.memory.remove(&start).unwrap();
+            let end = start + data.len() as u64;
+            if end > to {
+                let tail = data.split_off((to - start) as usize);
+                self.memory.insert(to, tail);
+            }
+            if start < from {
+                let piece = data.split_off((from - start) as usize);
+                self.memory.insert(start, data);
+                pieces.push((from, piece));
+            } else {
+                pieces.push((start, data));
+            }
+        }
+        self.memory_bytes -= pieces.iter().map(|(_, data)| data.len() as u64).sum::<u64>();
+        pieces
+    }
+
+    /// Brings memory back under the limit, down to three quarters of it so the copying this
+    /// takes is spread over many chunks. Returns the offset bytes were discarded up to.
+    fn evict(&mut self, head: u64) -> Option<u64> {
+        let limit = match self.overflow {
+            Overflow::Unbounded => return None,
+            Overflow::Spill(limit) | Overflow::Discard(limit) => limit,
+        };
+        if self.memory_bytes <= limit {
+            return None;
+        }
+        let target = limit / 4 * 3;
+
+        if let Overflow::Discard(_) = self.overflow {
+            let mut discarded = None;
+            while self.memory_bytes > target {
+                let Some((&start, _)) = self.memory.first_key_value() else {
+                    break;
+                };
+                let to = start + self.memory_bytes - target;
+                self.cut(start, to);
+                discarded = Some(to);
+            }
+            return discarded;
+        }
+
+        if self.spill.is_none() && !self.spill_failed {
+            match SpillFile::create() {
+                Ok(spill) => self.spill = Some(spill),
+                Err(e) => {
+                    self.spill_error = Some(format!("Failed to create stream spill file: {}", e));
+                    self.spill_failed = true;
+                }
+            }
+        }
+        while self.memory_bytes > target && self.spill.is_some() && !self.spill_failed {
+            let excess = self.memory_bytes - target;
+            let (from, to) = match (self.memory.first_key_value(), self.memory.last_key_value()) {
+                (Some((&start, _)), _) if start < head => (start, (start + excess).min(head)),
+                (_, Some((&start, data))) => {
+                    let end = start + data.len() as u64;
+                    (end.saturating_sub(excess).max(start).max(head), end)
+                }
+                _ => break,
+            };
+            if from >= to {
+                break;
+            }
+            let mut pieces = self.cut(from, to).into_iter();
+            let spill = self.spill.as_mut().unwrap();
+            let failed = pieces.by_ref().find_map(|(offset, data)| {
+                spill.write(offset, &data).err().map(|e| (e, offset, data))
+            });
+            if let Some((e, offset, data)) = failed {
+                self.spill_error = Some(format!("Failed to spill stream bytes: {}", e));
+                // Keep the bytes rather than lose them, what was spilled before stays readable
+                self.write(offset, &data);
+                for (offset, data) in pieces {
+                    self.write(offset, &data);
+                }
+                self.spill_failed = true;
+            }
+        }
+        None
+    }
+
+    fn memory_end_at(&self, position: u64) -> Option<u64> {
+        self.memory
+            .range(..=position)
+            .next_back()
+            .map(|(&start, data)| start + data.len() as u64)
+            .filter(|&end| end > position)
+    }
+}
+
+/// Bytes of a single URL stream, shared between its download task and its `StreamingSource`.
+///
+/// Downloads may start at any offset, so the bytes are kept as ranges. Memory use is bounded
+/// by the stream's `Overflow`.
+pub struct StreamBuffer {
+    storage: Mutex<Storage>,
+    pub is_ended: AtomicBool,
+    /// Set when the download gave up with bytes still missing.
+    pub failed: AtomicBool,
+    /// Set once nothing reads the stream any more, its download tasks stop on it.
+    cancelled: AtomicBool,
+    cancel_notify: Notify,
+    data_available: (Mutex<bool>, Condvar),
+    /// Earliest offset a reader is blocked on, for the download task to jump to.
+    wanted: Mutex<Option<u64>>,
+    pub wanted_changed: Notify,
+    /// Where playback last read, which bytes are kept in memory around.
+    read_head: AtomicU64,
+    /// Offset below which bytes were discarded.
+    discarded: AtomicU64,
+    pub duration: Mutex<Option<Duration>>,
+    /// Total size announced by the server, if any.
+    pub length: Option<u64>,
+}
+
+impl StreamBuffer {
+    pub fn new(length: Option<u64>, overflow: Overflow) -> Self {
+        StreamBuffer {
+            storage: Mutex::new(Storage {
+                memory: BTreeMap::new(),
+                memory_bytes: 0,
+                overflow,
+                spill: None,
+                spill_failed: false,
+                spill_error: None,
+                cached: None,
+            }),
+            is_ended: AtomicBool::new(false),
+            failed: AtomicBool::new(false),
+            cancelled: AtomicBool::new(false),
+            cancel_notify: Notify::new(),
+            data_available: (Mutex::new(false), Condvar::new()),
+            wanted: Mutex::new(None),
+            wanted_changed: Notify::new(),
+            read_head: AtomicU64::new(0),
+            discarded: AtomicU64::new(0),
+            duration: Mutex::new(None),
+            length,
+        }
+    }
+
+    /// Changes what happens to bytes over the memory limit from the next write on.
+    pub fn set_overflow(&self, overflow: Overflow) {
+        self.storage.lock().unwrap().overflow = overflow;
+    }
+
+    /// Stores `bytes` downloaded at `offset`, merging them with the ranges they touch.
+    /// Ignored once the stream is cancelled.
+    pub fn write(&self, offset: u64, bytes: &[u8]) {
+        let mut storage = self.storage.lock().unwrap();
+        if self.is_cancelled() {
+            return;
+        }
+        storage.write(offset, bytes);
+        if let Some(discarded) = storage.evict(self.read_head.load(Ordering::Relaxed)) {
+            self.discarded.fetch_max(discarded, Ordering::Relaxed);
+        }
+    }
+
+    /// Why the buffer could not spill to disk, once. Playback goes on from memory regardless.
+    pub fn take_spill_error(&self) -> Option<String> {
+        self.storage.lock().unwrap().spill_error.take()
+    }
+
+    /// Copies downloaded bytes at `position` into `buf`, `None` when that byte is missing.
+    pub fn read_at(&self, position: u64, buf: &mut [u8]) -> Option<usize> {
+        let mut storage = self.storage.lock().unwrap();
+        if let Some(end) = storage.memory_end_at(position) {
+            let (&start, data) = storage.memory.range(..=position).next_back()?;
+            let offset = (position - start) as usize;
+            let to_read = buf.len().min((end - position) as usize);
+            buf[..to_read].copy_from_slice(&data[offset..offset + to_read]);
+            return Some(to_read);
+        }
+        if let Some(read) = storage.spill.as_mut().and_then(|s| s.read_at(position, buf)) {
+            return Some(read);
+        }
+        storage.cached.as_mut()?.read_at(position, buf)
+    }
+
+    /// Serves the given ranges of the cache file at `path` without loading them into memory.
+    pub fn attach_cache(&self, path: &Path, ranges: &[(u64, u64)]) -> io::Result<()> {
+        self.storage.lock().unwrap().cached = Some(SpillFile::open_cached(path, ranges)?);
+        Ok(())
+    }
+
+    /// Records where playback reads, so eviction keeps the bytes around it in memory.
+    pub fn set_read_head(&self, position: u64) {
+        self.read_head.store(position, Ordering::Relaxed);
+    }
+
+    pub fn read_head(&self) -> u64 {
+        self.read_head.load(Ordering::Relaxed)
+    }
+
+    /// First missing byte at or after `position`.
+    pub fn next_missing(&self, position: u64) -> u64 {
+        let storage = self.storage.lock().unwrap();
+        let mut position = position;
+        // Memory, spilled and cached ranges may continue each other
+        loop {
+            let end = storage
+                .memory_end_at(position)
+                .or_else(|| storage.spill.as_ref().and_then(|s| s.end_at(position)))
+                .or_else(|| storage.cached.as_ref().and_then(|c| c.end_at(position)));
+            match end {
+                Some(end) => position = end,
+                None => return position,
+            }
+        }
+    }
+
+    pub fn buffered_bytes(&self) -> u64 {
+        let storage = self.storage.lock().unwrap();
+        storage.memory_bytes
+            + storage.spill.as_ref().map_or(0, SpillFile::len)
+            + storage.cached.as_ref().map_or(0, SpillFile::len)
+    }
+
+    /// First offset that has not been discarded.
+    pub fn discarded(&self) -> u64 {
+        self.discarded.load(Ordering::Relaxed)
+    }
+
+    /// Whether every byte of a stream with a known length has been downloaded.
+    pub fn is_complete(&self) -> bool {
+        self.length
+            .is_some_and(|length| self.next_missing(0) >= length)
+    }
+
+    /// Stops the tasks downloading this stream and wakes any reader waiting on it.
+    pub fn cancel(&self) {
+        {
+            // Taken so a `write` in progress finishes before the flag is visible
+            let _storage = self.storage.lock().unwrap();
+            self.cancelled.store(true, Ordering::Release);
+        }
+        self.cancel_notify.notify_waiters();
+        self.notify_data();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Acquire)
+    }
+
+    /// Completes once the stream has been cancelled.
+    pub async fn cancelled(&self) {
+        loop {
+            // Registered before the check so a cancel in between is not missed
+            let notified = self.cancel_notify.notified();
+            if self.is_cancelled() {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    /// Asks the download task for the byte at `position`.
+    pub fn request(&self, position: u64) {
+        let mut wanted = self.wanted.lock().unwrap();
+        if wanted.is_none_or(|current| position != current) {
+            *wanted = Some(position);
+            self.wanted_changed.notify_one();
+        }
+    }
+
+    pub fn take_wanted(&self) -> Option<u64> {
+        self.wanted.lock().unwrap().take()
+    }
+
+    pub fn notify_data(&self) {
+        let (lock, cvar) = &self.data_available;
+        let mut available = lock.lock().unwrap();
+        *available = true;
+        cvar.notify_one();
+    }
+
+    pub fn wait_for_data(&self) {
+        let (lock, cvar) = &self.data_available;
+        let mut available = lock.lock().unwrap();
+        if !*available {
+            available = cvar.wait(available).unwrap();
+        }
+        *available = false;
+    }
+}
+
+/// Random-access reader over a `StreamBuffer`.
+///
+/// In blocking mode reads past the downloaded bytes wait for the download task, otherwise they
+/// fail so a seek into a region that has not arrived yet can be reported instead of stalling
+/// the audio thread. Either way the missing offset is requested from the download task.
+pub struct StreamReader {
+    stream: Arc<StreamBuffer>,
+    position: u64,
+    blocking: Arc<AtomicBool>,
+}
+
+impl StreamReader {
+    pub fn new(stream: Arc<StreamBuffer>, blocking: Arc<AtomicBool>) -> Self {
+        StreamReader {
+            stream,
+            position: 0,
+            blocking,
+        }
+    }
+}
+
+impl Read for StreamReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            // Endless streams drop what was played long ago, or missed while paused. The
+            // demuxer resyncs on the next frame after the gap.
+            self.position = self.position.max(self.stream.discarded());
+            if let Some(read) = self.stream.read_at(self.position, buf) {
+                self.position += read as u64;
+                self.stream.set_read_head(self.position);
+                return Ok(read);
+            }
+            let past_end = self
+                .stream
+                .length
+                .is_some_and(|length| self.position >= length);
+            if past_end
+                || self.stream.is_ended.load(Ordering::Relaxed)
+                || self.stream.is_cancelled()
+            {
+                return Ok(0);
+            }
+
+            self.stream.request(self.position);
+            if !self.blocking.load(Ordering::Relaxed) {
+                return Err(io::Error::new(
+                    io::ErrorKind::WouldBlock,
+                    "position not downloaded yet",
+                ));
+            }
+            self.stream.wait_for_data();
+        }
+    }
+}
+
+impl Seek for StreamReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let new_pos = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+            SeekFrom::End(offset) => self
+                .byte_len()
+                .ok_or_else(|| io::Error::new(io::ErrorKind::Unsupported, "unknown stream length"))?
+                .checked_add_signed(offset),
+        };
+
+        self.position = new_pos
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid seek position"))?;
+        Ok(self.position)
+    }
+}
+
+impl MediaSource for StreamReader {
+    fn is_seekable(&self) -> bool {
+        true
+    }
+
+    fn byte_len(&self) -> Option<u64> {
+        self.stream.length.or_else(|| {
+            self.stream
+                .is_ended
+                .load(Ordering::Relaxed)
+                .then(|| self.stream.next_missing(0))
+        })
+    }
+}
+
+/// Symphonia demuxer and codec over a `StreamReader`, producing interleaved `i16` samples.
+pub struct StreamDecoder {
+    format: Box<dyn FormatReader>,
+    decoder: Box<dyn CodecDecoder>,
+    track_id: u32,
+    time_base: Option<TimeBase>,
+    buffer: Option<SampleBuffer<i16>>,
+    samples: Vec<i16>,
+    position: usize,
+    /// Samples still to drop after an accurate seek landed before the requested timestamp.
+    skip: usize,
+    sample_rate: u32,
+    channels: u16,
+}
+
+impl StreamDecoder {
+    /// Opens `stream` and moves to `pos` through the format reader's own seek logic (FLAC seek
+    /// tables, MP4 sample tables, Ogg page bisection, ...), without waiting for bytes that have
+    /// not been downloaded yet. Reads block normally once the seek has succeeded.
+    pub fn open_at(stream: Arc<StreamBuffer>, pos: Duration) -> Result<Self, SymphoniaError> {
+        let blocking = Arc::new(AtomicBool::new(false));
+        let reader = StreamReader::new(stream, blocking.clone());
+        let mut decoder = Self::probe(reader, None)?;
+        decoder.seek(pos)?;
+        blocking.store(true, Ordering::Relaxed);
+        Ok(decoder)
+    }
+
+    /// Opens `stream` from its start, waiting for bytes that have not arrived yet. `extension`
+    /// hints at the container of streams that come without a file name.
+    pub fn open(stream: Arc<StreamBuffer>, extension: Option<&str>) -> Result<Self, SymphoniaError> {
+        let reader = StreamReader::new(stream, Arc::new(AtomicBool::new(true)));
+        Self::probe(reader, extension)
+    }
+
+    fn probe(reader: StreamReader, extension: Option<&str>) -> Result<Self, SymphoniaError> {
+        let source = MediaSourceStream::new(Box::new(reader), Default::default());
+        let mut hint = Hint::new();
+        if let Some(extension) = extension {
+            hint.with_extension(extension);
+        }
+        let probed = symphonia::default::get_probe().format(
+            &hint,
+            source,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )?;
+        let format = probed.format;
+
+        let track = format
+            .tracks()
+            .iter()
+            .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
+            .ok_or(SymphoniaError::Unsupported("no audio track"))?;
+        let decoder = symphonia::default::get_codecs()
+            .make(&track.codec_params, &DecoderOptions::default())?;
+
+        Ok(StreamDecoder {
+            track_id: track.id,
+            time_base: track.codec_params.time_base,
+            sample_rate: track.codec_params.sample_rate.unwrap_or(44100),
+            channels: track
+                .codec_params
+                .channels
+                .map(|c| c.count() as u16)
+                .unwrap_or(2),
+            format,
+            decoder,
+            buffer: None,
+            samples: Vec::new(),
+            position: 0,
+            skip: 0,
+        })
+    }
+
+    fn seek(&mut self, pos: Duration) -> Result<(), SymphoniaError> {
+        let seeked = self.format.seek(
+            SeekMode::Accurate,
+            SeekTo::Time {
+                time: Time::new(pos.as_secs(), pos.subsec_nanos() as f64 / 1e9),
+                track_id: Some(self.track_id),
+            },
+        )?;
+        self.decoder.reset();
+        self.samples.clear();
+        self.position = 0;
+
+        let frames = seeked.required_ts.saturating_sub(seeked.actual_ts);
+        let frames = match self.time_base {
+            Some(tb) => frames as f64 * tb.numer as f64 / tb.denom as f64 * self.sample_rate as f64,
+            None => frames as f64,
+        };
+        self.skip = frames as usize * self.channels as usize;
+        Ok(())
+    }
+
+    /// Switches to the audio track of a format reader whose track list changed.
+    fn reset_track(&mut self) -> Result<(), SymphoniaError> {
+        let track = self
+            .format
+            .tracks()
+            .iter()
+            .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
+            .ok_or(SymphoniaError::Unsupported("no audio track"))?;
+        self.decoder = symphonia::default::get_codecs()
+            .make(&track.codec_params, &DecoderOptions::default())?;
+        self.track_id = track.id;
+        self.time_base = track.codec_params.time_base;
+        Ok(())
+    }
+
+    /// Decodes the next packet of our track into `samples`, returning `false` at the end.
+    fn decode_packet(&mut self) -> bool {
+        loop {
+            let packet = match self.format.next_packet() {
+                Ok(packet) => packet,
+                // Chained Ogg radio streams start a new logical stream on every song
+                Err(SymphoniaError::ResetRequired) => match self.reset_track() {
+                    Ok(()) => continue,
+                    Err(_) => return false,
+                },
+                Err(_) => return false,
+            };
+            if packet.track_id() != self.track_id {
+                continue;
+            }
+
+            let decoded = match self.decoder.decode(&packet) {
+                Ok(decoded) => decoded,
+                // Corrupt packets are dropped rather than ending the track
+                Err(SymphoniaError::DecodeError(_)) => continue,
+                Err(_) => return false,
+            };
+
+            let spec = *decoded.spec();
+            let frames = decoded.capacity() as u64;
+            let channels = spec.channels.count() as u16;
+            let reusable = self
+                .buffer
+                .as_ref()
+                .is_some_and(|b| b.capacity() as u64 >= frames)
+                && self.sample_rate == spec.rate
+                && self.channels == channels;
+            if !reusable {
+                self.buffer = Some(SampleBuffer::new(frames, spec));
+            }
+            let buffer = self.buffer.as_mut().unwrap();
+            buffer.copy_interleaved_ref(decoded);
+
+            self.sample_rate = spec.rate;
+            self.channels = channels;
+            self.samples.clear();
+            self.samples.extend_from_slice(buffer.samples());
+            self.position = 0;
+            return true;
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    /// Samples left of the current packet. The next packet is decoded as soon as one runs
+    /// out, so this is only `None` at the end, never an empty span.
+    pub fn current_span_len(&self) -> Option<usize> {
+        (self.position < self.samples.len()).then(|| self.samples.len() - self.position)
+    }
+}
+
+impl Iterator for StreamDecoder {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        loop {
+            while self.position == self.samples.len() {
+                if !self.decode_packet() {
+                    return None;
+                }
+            }
+            let sample = self.samples[self.position];
+            self.position += 1;
+            if self.position == self.samples.len() {
+                // Failing here is retried, and reported, by the next call
+                self.decode_packet();
+            }
+            if self.skip == 0 {
+                return Some(sample);
+            }
+            self.skip -= 1;
+        }
+    }
+}
@@ -0,0 +1,378 @@
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Overlap between consecutive segments, which are twice as long.
+const OVERLAP: Duration = Duration::from_millis(20);
+/// How far a segment may move from its nominal position to line up with the previous one.
+const SEEK_WINDOW: Duration = Duration::from_millis(8);
+pub const MIN_SPEED: f32 = 0.25;
+pub const MAX_SPEED: f32 = 4.0;
+/// Pitch shift range either way, one octave.
+pub const MAX_PITCH_CENTS: f32 = 1200.0;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum SpeedMode {
+    /// Time-stretches the audio so voices keep their pitch.
+    #[default]
+    PreservePitch,
+    /// Resamples on the sink, which shifts the pitch along with the speed.
+    Resample,
+}
+
+#[derive(Clone, Copy)]
+struct TempoSettings {
+    speed: f32,
+    mode: SpeedMode,
+    pitch_cents: f32,
+}
+
+/// Playback speed and pitch shift shared with every `TimeStretch` and pitch shift stage.
+pub struct TempoControl {
+    settings: Mutex<TempoSettings>,
+    /// Factor the stretch stages apply. It leaves out speed the sink resamples by and makes
+    /// up for the tempo change the pitch shift brings along.
+    tempo: AtomicU32,
+    /// Rate the pitch shift stages read their input at.
+    pitch: AtomicU32,
+}
+
+impl Default for TempoControl {
+    fn default() -> Self {
+        TempoControl {
+            settings: Mutex::new(TempoSettings {
+                speed: 1.0,
+                mode: SpeedMode::default(),
+                pitch_cents: 0.0,
+            }),
+            tempo: AtomicU32::new(1f32.to_bits()),
+            pitch: AtomicU32::new(1f32.to_bits()),
+        }
+    }
+}
+
+impl TempoControl {
+    pub fn speed(&self) -> f32 {
+        self.settings.lock().unwrap().speed
+    }
+
+    pub fn mode(&self) -> SpeedMode {
+        self.settings.lock().unwrap().mode
+    }
+
+    pub fn pitch_cents(&self) -> f32 {
+        self.settings.lock().unwrap().pitch_cents
+    }
+
+    /// Stores the speed and mode, returning the speed the sink has to resample by.
+    pub fn set(&self, speed: f32, mode: SpeedMode) -> f32 {
+        let mut settings = self.settings.lock().unwrap();
+        settings.speed = speed;
+        settings.mode = mode;
+        self.update(&settings)
+    }
+
+    pub fn set_pitch(&self, cents: f32) {
+        let mut settings = self.settings.lock().unwrap();
+        settings.pitch_cents = cents;
+        self.update(&settings);
+    }
+
+    fn update(&self, settings: &TempoSettings) -> f32 {
+        let (tempo, sink_speed) = match settings.mode {
+            SpeedMode::PreservePitch => (settings.speed, 1.0),
+            SpeedMode::Resample => (1.0, settings.speed),
+        };
+        let pitch = 2f32.powf(settings.pitch_cents / 1200.0);
+        self.tempo.store((tempo / pitch).to_bits(), Ordering::Release);
+        self.pitch.store(pitch.to_bits(), Ordering::Release);
+        sink_speed
+    }
+
+    fn tempo(&self) -> f32 {
+        f32::from_bits(self.tempo.load(Ordering::Acquire))
+    }
+
+    pub fn pitch(&self) -> f32 {
+        f32::from_bits(self.pitch.load(Ordering::Acquire))
+    }
+}
+
+/// Media position of one track. The sink only counts stretched output, so the
+/// `TimeStretch` stage keeps this instead.
+#[derive(Default)]
+pub struct MediaClock {
+    nanos: AtomicU64,
+}
+
+impl MediaClock {
+    pub fn position(&self) -> Duration {
+        Duration::from_nanos(self.nanos.load(Ordering::Relaxed))
+    }
+
+    fn set(&self, seconds: f64) {
+        self.nanos
+            .store((seconds.max(0.0) * 1e9) as u64, Ordering::Relaxed);
+    }
+}
+
+/// WSOLA time stretch: overlapping segments are taken from the input at the tempo's pace,
+/// each shifted within a small window to where it best continues the previous one, and
+/// crossfaded at the original rate so the pitch stays put.
+pub struct TimeStretch<S> {
+    inner: S,
+    control: Arc<TempoControl>,
+    clock: Arc<MediaClock>,
+    /// Media position in seconds of the next frame handed out.
+    position: f64,
+    channel: usize,
+    channels: usize,
+    sample_rate: u32,
+    /// Overlap and seek window in frames for the current format.
+    overlap: usize,
+    seek: usize,
+    fade_in: Vec<f32>,
+    /// Interleaved input not consumed yet. Frame positions below are relative to its start.
+    input: Vec<f32>,
+    /// Second half of the last segment, faded out under the next one. `None` while passing through.
+    tail: Option<Vec<f32>>,
+    /// Nominal start of the next segment.
+    read: f64,
+    /// Frame right after `tail`, where plain playback picks up again.
+    resume: usize,
+    output: Vec<f32>,
+    cursor: usize,
+    /// Media frames each frame of `output` stands for.
+    step: f64,
+}
+
+impl<S> TimeStretch<S>
+where
+    S: Source<Item = f32>,
+{
+    pub fn new(inner: S, control: Arc<TempoControl>, clock: Arc<MediaClock>) -> Self {
+        TimeStretch {
+            channels: inner.channels().max(1) as usize,
+            sample_rate: inner.sample_rate(),
+            inner,
+            control,
+            clock,
+            position: 0.0,
+            channel: 0,
+            overlap: 0,
+            seek: 0,
+            fade_in: Vec::new(),
+            input: Vec::new(),
+            tail: None,
+            read: 0.0,
+            resume: 0,
+            output: Vec::new(),
+            cursor: 0,
+            step: 1.0,
+        }
+    }
+
+    /// Pulls input until it holds `frames` frames, false once the inner source ran out.
+    fn fill(&mut self, frames: usize) -> bool {
+        while self.input.len() < frames * self.channels {
+            match self.inner.next() {
+                Some(sample) => self.input.push(sample),
+                None => return false,
+            }
+        }
+        true
+    }
+
+    /// Takes the first segment from the input, played as is up to its second half.
+    fn start(&mut self, tempo: f32) {
+        self.channels = self.inner.channels().max(1) as usize;
+        self.sample_rate = self.inner.sample_rate();
+        let rate = self.sample_rate as f32;
+        self.overlap = ((OVERLAP.as_secs_f32() * rate) as usize).max(1);
+        self.seek = (SEEK_WINDOW.as_secs_f32() * rate) as usize;
+        self.fade_in = (0..self.overlap)
+            .map(|i| 0.5 - 0.5 * (PI * (i as f32 + 0.5) / self.overlap as f32).cos())
+            .collect();
+
+        let (l, ch) = (self.overlap, self.channels);
+        if !self.fill(2 * l) {
+            self.finish();
+            return;
+        }
+        self.output.extend_from_slice(&self.input[..l * ch]);
+        self.tail = Some(self.input[l * ch..2 * l * ch].to_vec());
+        self.read = l as f64 * tempo as f64;
+        self.resume = 2 * l;
+        self.step = 1.0;
+    }
+
+    /// Crossfades the tail into the best matching segment around the nominal position.
+    fn advance(&mut self, tempo: f32) {
+        let (l, ch) = (self.overlap, self.channels);
+        let nominal = self.read.round() as usize;
+        let (lo, hi) = (nominal.saturating_sub(self.seek), nominal + self.seek);
+        if !self.fill(hi + 2 * l) {
+            self.finish();
+            return;
+        }
+
+        let Some(tail) = self.tail.take() else {
+            return;
+        };
+        let best = best_offset(&self.input, &tail, lo, hi, l, ch);
+        for (i, &w) in self.fade_in.iter().enumerate() {
+            for c in 0..ch {
+                let incoming = self.input[(best + i) * ch + c];
+                self.output.push(tail[i * ch + c] * (1.0 - w) + incoming * w);
+            }
+        }
+        self.tail = Some(self.input[(best + l) * ch..(best + 2 * l) * ch].to_vec());
+        self.resume = best + 2 * l;
+        self.read += l as f64 * tempo as f64;
+        self.step = tempo as f64;
+
+        // Keep what the next search window and the resume point still need
+        let consumed = (self.read as usize).saturating_sub(self.seek).min(self.resume);
+        self.input.drain(..consumed * ch);
+        self.read -= consumed as f64;
+        self.resume -= consumed;
+    }
+
+    /// Hands out the tail and the input after it unchanged and returns to plain playback.
+    fn finish(&mut self) {
+        if let Some(tail) = self.tail.take() {
+            self.output.extend_from_slice(&tail);
+            let resume = (self.resume * self.channels).min(self.input.len());
+            self.output.extend_from_slice(&self.input[resume..]);
+        } else {
+            self.output.extend_from_slice(&self.input);
+        }
+        self.input.clear();
+        self.step = 1.0;
+    }
+
+    fn reset(&mut self) {
+        self.input.clear();
+        self.tail = None;
+        self.output.clear();
+        self.cursor = 0;
+        self.channel = 0;
+    }
+
+    /// Counts one handed out sample towards the media position.
+    fn tick(&mut self, step: f64, channels: usize) {
+        self.channel += 1;
+        if self.channel >= channels {
+            self.channel = 0;
+            self.position += step / self.sample_rate.max(1) as f64;
+            self.clock.set(self.position);
+        }
+    }
+}
+
+impl<S> Iterator for TimeStretch<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.cursor == self.output.len() {
+            self.output.clear();
+            self.cursor = 0;
+
+            let tempo = self.control.tempo();
+            let format_changed = self.inner.channels().max(1) as usize != self.channels
+                || self.inner.sample_rate() != self.sample_rate;
+            match self.tail {
+                Some(_) if tempo == 1.0 || format_changed => self.finish(),
+                Some(_) => self.advance(tempo),
+                None if tempo != 1.0 => self.start(tempo),
+                None => {
+                    self.channels = self.inner.channels().max(1) as usize;
+                    self.sample_rate = self.inner.sample_rate();
+                    let sample = self.inner.next()?;
+                    self.tick(1.0, self.channels);
+                    return Some(sample);
+                }
+            }
+            if self.output.is_empty() {
+                return None;
+            }
+        }
+
+        let sample = self.output[self.cursor];
+        self.cursor += 1;
+        self.tick(self.step, self.channels);
+        Some(sample)
+    }
+}
+
+impl<S> Source for TimeStretch<S>
+where
+    S: Source<Item = f32>,
+{
+    fn current_span_len(&self) -> Option<usize> {
+        match self.tail {
+            None if self.cursor == self.output.len() => self.inner.current_span_len(),
+            _ => None,
+        }
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels as u16
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), rodio::source::SeekError> {
+        self.inner.try_seek(pos)?;
+        self.reset();
+        self.position = pos.as_secs_f64();
+        self.clock.set(self.position);
+        Ok(())
+    }
+}
+
+/// Start frame within `lo..=hi` whose first `len` frames correlate best with `reference`,
+/// compared on a mono mix.
+fn best_offset(
+    input: &[f32],
+    reference: &[f32],
+    lo: usize,
+    hi: usize,
+    len: usize,
+    channels: usize,
+) -> usize {
+    let mono = |buffer: &[f32], frame: usize| -> f32 {
+        buffer[frame * channels..(frame + 1) * channels].iter().sum()
+    };
+    let reference: Vec<f32> = (0..len).map(|i| mono(reference, i)).collect();
+    let candidate: Vec<f32> = (lo..hi + len).map(|i| mono(input, i)).collect();
+
+    let mut energy: f32 = candidate[..len].iter().map(|x| x * x).sum();
+    let mut best = (lo, f32::MIN);
+    for offset in 0..=hi - lo {
+        if offset > 0 {
+            let (gone, added) = (candidate[offset - 1], candidate[offset + len - 1]);
+            energy = (energy - gone * gone + added * added).max(0.0);
+        }
+        let window = &candidate[offset..offset + len];
+        let correlation: f32 = window.iter().zip(&reference).map(|(a, b)| a * b).sum();
+        let score = correlation / (energy + 1e-9).sqrt();
+        if score > best.1 {
+            best = (lo + offset, score);
+        }
+    }
+    best.0
+}
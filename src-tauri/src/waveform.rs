@@ -0,0 +1,220 @@
+use crate::audio::AudioState;
+use crate::error::AppError;
+use crate::loudness::file_mtime;
+use rodio::{Decoder, Sample, Source};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tauri::State;
+use tokio::sync::Semaphore;
+
+/// Peaks stored per track; requests at lower resolutions are downsampled from these.
+pub const MAX_RESOLUTION: usize = 2048;
+/// Frames folded into one peak while decoding, before the final downsampling.
+const BLOCK_FRAMES: usize = 256;
+
+/// Min/max peaks quantized to `i8`, keyed by song id. Kept as one small file per track in the
+/// app cache directory, or in memory when there is none.
+#[derive(Default)]
+pub struct WaveformStore {
+    dir: Option<PathBuf>,
+    memory: HashMap<String, (u64, Vec<[i8; 2]>)>,
+    /// One lock per track being computed, so concurrent requests decode it only once.
+    computing: HashMap<String, Arc<tokio::sync::Mutex<()>>>,
+    decoders: Option<Arc<Semaphore>>,
+}
+
+impl WaveformStore {
+    pub fn load(&mut self, dir: &Path) {
+        self.dir = Some(dir.to_path_buf());
+    }
+
+    fn file(&self, id: &str) -> Option<PathBuf> {
+        let mut hasher = DefaultHasher::new();
+        id.hash(&mut hasher);
+        Some(self.dir.as_ref()?.join(format!("{:016x}", hasher.finish())))
+    }
+
+    /// Returns the stored peaks for `id` when they were computed from the same `mtime`.
+    fn get(&self, id: &str, mtime: u64) -> Option<Vec<[i8; 2]>> {
+        let Some(file) = self.file(id) else {
+            return self
+                .memory
+                .get(id)
+                .filter(|(stored, _)| *stored == mtime)
+                .map(|(_, peaks)| peaks.clone());
+        };
+        let data = fs::read(file).ok()?;
+        let (stored, peaks) = data.split_at_checked(8)?;
+        if u64::from_le_bytes(stored.try_into().ok()?) != mtime {
+            return None;
+        }
+        Some(
+            peaks
+                .chunks_exact(2)
+                .map(|p| [p[0] as i8, p[1] as i8])
+                .collect(),
+        )
+    }
+
+    fn insert(&mut self, id: &str, mtime: u64, peaks: Vec<[i8; 2]>) {
+        let Some(file) = self.file(id) else {
+            self.memory.insert(id.to_string(), (mtime, peaks));
+            return;
+        };
+        let mut data = mtime.to_le_bytes().to_vec();
+        data.extend(peaks.iter().flat_map(|p| [p[0] as u8, p[1] as u8]));
+        if let Some(dir) = file.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        let _ = fs::write(file, data);
+    }
+
+    fn lock_for(&mut self, id: &str) -> Arc<tokio::sync::Mutex<()>> {
+        self.computing.entry(id.to_string()).or_default().clone()
+    }
+
+    fn decoders(&mut self) -> Arc<Semaphore> {
+        self.decoders
+            .get_or_insert_with(|| Arc::new(Semaphore::new(num_cpus::get().max(1))))
+            .clone()
+    }
+}
+
+/// Removes the entry for `id` from `computing` once its last user is done with it, whether
+/// the waveform was computed or failed.
+struct ComputingGuard {
+    store: Arc<Mutex<WaveformStore>>,
+    id: String,
+    lock: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl Drop for ComputingGuard {
+    fn drop(&mut self) {
+        let mut store = self.store.lock().unwrap();
+        if Arc::strong_count(&self.lock) == 2 {
+            store.computing.remove(&self.id);
+        }
+    }
+}
+
+/// Decodes the whole file into `MAX_RESOLUTION` min/max peaks over all channels.
+fn decode_peaks(path: &Path) -> Result<Vec<[i8; 2]>, AppError> {
+    let file = File::open(path).map_err(|e| AppError::FileOpenError(e.to_string()))?;
+    let decoder =
+        Decoder::new(BufReader::new(file)).map_err(|e| AppError::DecodeError(e.to_string()))?;
+
+    let block = BLOCK_FRAMES * decoder.channels().max(1) as usize;
+    let mut blocks: Vec<[f32; 2]> = Vec::new();
+    let mut current = [0.0f32; 2];
+    for (i, sample) in decoder.enumerate() {
+        let sample = sample.to_f32();
+        current = [current[0].min(sample), current[1].max(sample)];
+        if (i + 1) % block == 0 {
+            blocks.push(current);
+            current = [0.0; 2];
+        }
+    }
+    if current != [0.0; 2] {
+        blocks.push(current);
+    }
+    if blocks.is_empty() {
+        return Err(AppError::DecodeError("Track has no samples".to_string()));
+    }
+
+    Ok(downsample(&blocks, MAX_RESOLUTION)
+        .into_iter()
+        .map(|[min, max]| [(min * 127.0).round() as i8, (max * 127.0).round() as i8])
+        .collect())
+}
+
+/// Folds `peaks` into `resolution` buckets, or fewer when there are not that many.
+fn downsample<T: Copy + PartialOrd>(peaks: &[[T; 2]], resolution: usize) -> Vec<[T; 2]> {
+    let buckets = resolution.min(peaks.len());
+    (0..buckets)
+        .map(|bucket| {
+            let range = &peaks[bucket * peaks.len() / buckets..(bucket + 1) * peaks.len() / buckets];
+            range.iter().skip(1).fold(range[0], |[min, max], p| {
+                [
+                    if p[0] < min { p[0] } else { min },
+                    if p[1] > max { p[1] } else { max },
+                ]
+            })
+        })
+        .collect()
+}
+
+/// Returns `resolution` `[min, max]` peak pairs for a song, decoding the local file at `path`
+/// or its fully cached stream in the background the first time.
+#[tauri::command]
+pub async fn get_waveform(
+    state: State<'_, AudioState>,
+    id: String,
+    path: Option<String>,
+    resolution: usize,
+) -> Result<Vec<[f32; 2]>, AppError> {
+    if resolution == 0 || resolution > MAX_RESOLUTION {
+        return Err(AppError::InvalidOperation(format!(
+            "Waveform resolution must be between 1 and {}",
+            MAX_RESOLUTION
+        )));
+    }
+
+    // Streams are cached under their id and never change, local files are checked by mtime
+    let (file, mtime) = match path {
+        Some(path) => {
+            let path = PathBuf::from(path);
+            let mtime = file_mtime(&path)
+                .ok_or_else(|| AppError::FileNotFound(path.to_string_lossy().into_owned()))?;
+            (path, mtime)
+        }
+        None => {
+            let cached = state.cache.lock().unwrap().lookup(&id);
+            match cached {
+                Some(cached) if cached.is_complete() => (cached.path().to_path_buf(), 0),
+                _ => {
+                    return Err(AppError::InvalidOperation(format!(
+                        "No local file or cached stream for {}",
+                        id
+                    )))
+                }
+            }
+        }
+    };
+
+    let lock = state.waveforms.lock().unwrap().lock_for(&id);
+    let computing = ComputingGuard {
+        store: state.waveforms.clone(),
+        id: id.clone(),
+        lock,
+    };
+    let peaks = {
+        let _computing = computing.lock.lock().await;
+        let stored = state.waveforms.lock().unwrap().get(&id, mtime);
+        match stored {
+            Some(peaks) => peaks,
+            None => {
+                let decoders = state.waveforms.lock().unwrap().decoders();
+                let _permit = decoders
+                    .acquire()
+                    .await
+                    .map_err(|e| AppError::DecodeError(e.to_string()))?;
+                let peaks = tokio::task::spawn_blocking(move || decode_peaks(&file))
+                    .await
+                    .map_err(|e| AppError::DecodeError(e.to_string()))??;
+                state.waveforms.lock().unwrap().insert(&id, mtime, peaks.clone());
+                peaks
+            }
+        }
+    };
+    drop(computing);
+
+    Ok(downsample(&peaks, resolution)
+        .into_iter()
+        .map(|[min, max]| [min as f32 / 127.0, max as f32 / 127.0])
+        .collect())
+}
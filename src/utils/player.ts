@@ -130,7 +130,8 @@ function setupEventListeners () {
         if (!currentSong) return;
 
         const durationJotai = focusAtom(currentSongJotai as WritableAtom<Song<string>, [SetStateAction<Song<string>>], void>, (optic) => optic.prop('duration'));
-        sharedStore.set(durationJotai, e.payload);
+        // The backend reports seconds, songs keep milliseconds
+        sharedStore.set(durationJotai, e.payload * 1000);
     });
 
     listen<PlaybackPayload>('playback_event', (event) => {